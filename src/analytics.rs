@@ -0,0 +1,679 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::block::Block;
+use crate::blockchain::{apply_transaction, Blockchain};
+use crate::error::IssuanceError;
+use crate::transaction::{Transaction, SYSTEM_ADDRESS};
+
+// Read-only history and statistics queries over the chain
+
+impl Blockchain {
+    // Balance of `address` counting only blocks 0..=height. As in
+    // get_balance, System's is minus what was issued up to then.
+    pub fn balance_at(&self, address: &str, height: u64) -> f64 {
+        self.chain.iter()
+            .take_while(|block| block.index <= height)
+            .map(|block| balance_change(block, address))
+            .sum()
+    }
+
+    // (block index, balance_at that block) for every block that changed the
+    // balance of `address`, oldest first
+    pub fn balance_history(&self, address: &str) -> Vec<(u64, f64)> {
+        let mut history = Vec::new();
+        let mut balance = 0.0;
+
+        for block in &self.chain {
+            let delta = balance_change(block, address);
+            if delta != 0.0 {
+                balance += delta;
+                history.push((block.index, balance));
+            }
+        }
+
+        history
+    }
+
+    // Addresses that ended up below zero, sorted by address. System is
+    // skipped since every reward is paid out of it. A non-empty result means
+    // an overspend made it into the chain, e.g. through a hand-edited file.
+    pub fn find_negative_balances(&self) -> Vec<(String, f64)> {
+        let mut negative: Vec<(String, f64)> = self.balances()
+            .into_iter()
+            .filter(|(address, balance)| address != SYSTEM_ADDRESS && *balance < 0.0)
+            .collect();
+        negative.sort_by(|a, b| a.0.cmp(&b.0));
+        negative
+    }
+
+    // Every transaction stamped within from..=to, with the index of its
+    // block, in chain order
+    pub fn transactions_in_range(&self, from: i64, to: i64) -> Vec<(u64, &Transaction)> {
+        self.chain.iter()
+            .flat_map(|block| block.transactions.iter().map(move |tx| (block.index, tx)))
+            .filter(|(_, tx)| (from..=to).contains(&tx.timestamp))
+            .collect()
+    }
+
+    // Total amount sent a -> b and b -> a across the chain (fees excluded)
+    pub fn flow_between(&self, a: &str, b: &str) -> (f64, f64) {
+        let mut a_to_b = 0.0;
+        let mut b_to_a = 0.0;
+
+        for tx in self.chain.iter().flat_map(|block| &block.transactions) {
+            if tx.sender == a {
+                a_to_b += tx.received_by(b);
+            } else if tx.sender == b {
+                b_to_a += tx.received_by(a);
+            }
+        }
+
+        (a_to_b, b_to_a)
+    }
+
+    // Mean number of transactions in the blocks after genesis, optionally
+    // counting the reward transactions. 0 when nothing has been mined.
+    pub fn avg_transactions_per_block(&self, include_coinbase: bool) -> f64 {
+        let mined = &self.chain[1.min(self.chain.len())..];
+        if mined.is_empty() {
+            return 0.0;
+        }
+
+        let total: usize = mined.iter()
+            .map(|block| {
+                block.transactions.iter()
+                    .filter(|tx| include_coinbase || !tx.is_system())
+                    .count()
+            })
+            .sum();
+        total as f64 / mined.len() as f64
+    }
+
+    // Transaction count (reward included) of the fullest block
+    pub fn max_transactions_in_block(&self) -> usize {
+        self.chain.iter()
+            .map(Block::transaction_count)
+            .max()
+            .unwrap_or(0)
+    }
+
+    // Seconds between each block and the one before it, oldest first
+    pub fn block_times(&self) -> Vec<i64> {
+        self.chain.windows(2)
+            .map(|pair| pair[1].timestamp - pair[0].timestamp)
+            .collect()
+    }
+
+    // Mean of block_times, 0 when nothing has been mined
+    pub fn avg_block_time(&self) -> f64 {
+        let times = self.block_times();
+        if times.is_empty() {
+            return 0.0;
+        }
+        times.iter().sum::<i64>() as f64 / times.len() as f64
+    }
+
+    // Combined Block::size of every block, i.e. the transaction payload
+    pub fn estimated_size_bytes(&self) -> usize {
+        self.chain.iter().map(Block::size).sum()
+    }
+
+    // Hashes tried for block `index` (its nonce plus the initial hash) over
+    // the 16^difficulty expected on average. Below 1 means the miner got
+    // lucky. With several mining threads the nonce only approximates the
+    // attempts made.
+    pub fn block_luck(&self, index: u64) -> Option<f64> {
+        let block = self.chain.get(index as usize)?;
+        let expected = 16f64.powi(block.difficulty as i32);
+        Some((block.nonce as f64 + 1.0) / expected)
+    }
+
+    // What `address` got from the blocks it mined, split into (subsidy,
+    // fees). With a split reward each recipient's cut of the fees is in
+    // proportion to its share.
+    pub fn miner_earnings(&self, address: &str) -> (f64, f64) {
+        let mut rewards = 0.0;
+        let mut fees = 0.0;
+
+        for block in self.chain.iter().skip(1) {
+            let (paid, earned) = block.transactions.iter()
+                .filter(|tx| tx.is_system())
+                .fold((0.0, 0.0), |(paid, earned), tx| (paid + tx.amount, earned + tx.received_by(address)));
+            if earned <= 0.0 {
+                continue;
+            }
+
+            let block_fees: f64 = block.transactions.iter().map(|tx| tx.fee).sum();
+            let fee_part = block_fees * earned / paid;
+            fees += fee_part;
+            rewards += earned - fee_part;
+        }
+
+        (rewards, fees)
+    }
+
+    // Fees paid across the whole chain. They go to the miners, so none are
+    // burned.
+    pub fn total_fees(&self) -> f64 {
+        self.chain.iter()
+            .flat_map(|block| &block.transactions)
+            .map(|tx| tx.fee)
+            .sum()
+    }
+
+    pub fn fees_in_block(&self, index: u64) -> Option<f64> {
+        let block = self.chain.get(index as usize)?;
+        Some(block.transactions.iter().map(|tx| tx.fee).sum())
+    }
+
+    // Miner with the most blocks in a row after genesis, and how many.
+    // The earliest run wins a tie; ("", 0) when nothing has been mined.
+    pub fn longest_miner_streak(&self) -> (String, usize) {
+        let mut best: (Option<&str>, usize) = (None, 0);
+        let mut current: (Option<&str>, usize) = (None, 0);
+
+        for block in self.chain.iter().skip(1) {
+            let miner = block.miner();
+            current = if miner.is_some() && miner == current.0 { (miner, current.1 + 1) } else { (miner, 1) };
+            if miner.is_some() && current.1 > best.1 {
+                best = current;
+            }
+        }
+
+        (best.0.unwrap_or_default().to_string(), best.1)
+    }
+
+    // Transactions per second (rewards left out) over blocks stamped in the
+    // last `window_secs` seconds before `now`. 0 for an empty window.
+    pub fn tps(&self, window_secs: i64, now: i64) -> f64 {
+        if window_secs <= 0 {
+            return 0.0;
+        }
+
+        let count = self.chain.iter()
+            .filter(|block| block.timestamp > now - window_secs && block.timestamp <= now)
+            .flat_map(|block| &block.transactions)
+            .filter(|tx| !tx.is_system())
+            .count();
+        count as f64 / window_secs as f64
+    }
+
+    // Seconds from the tip's timestamp to `now`
+    pub fn time_since_last_block(&self, now: i64) -> i64 {
+        now - self.get_latest_block().timestamp
+    }
+
+    // True once more than `threshold` seconds have passed without a block
+    pub fn is_stalled(&self, now: i64, threshold: i64) -> bool {
+        self.time_since_last_block(now) > threshold
+    }
+
+    // Indices in 0..=tip that no block carries, lowest first. Always empty
+    // for a chain built by mining; a partial import can leave holes.
+    pub fn find_index_gaps(&self) -> Vec<u64> {
+        let Some(tip) = self.chain.iter().map(|block| block.index).max() else {
+            return Vec::new();
+        };
+        let present: HashSet<u64> = self.chain.iter().map(|block| block.index).collect();
+        (0..=tip).filter(|index| !present.contains(index)).collect()
+    }
+
+    // Transaction count (reward included) of every block, genesis first
+    pub fn tx_counts(&self) -> Vec<usize> {
+        self.chain.iter().map(Block::transaction_count).collect()
+    }
+
+    // Transactions across the whole chain, from the running offsets
+    pub fn total_transactions(&self) -> u64 {
+        match (self.tx_offsets.last(), self.chain.last()) {
+            (Some(offset), Some(last)) => offset + last.transaction_count() as u64,
+            _ => 0,
+        }
+    }
+
+    // Chain-wide position of transaction `tx_in_block` of block `block`,
+    // counting from 0 at the genesis transaction
+    pub fn global_tx_index(&self, block: u64, tx_in_block: usize) -> Option<u64> {
+        let transactions = &self.chain.get(block as usize)?.transactions;
+        if tx_in_block >= transactions.len() {
+            return None;
+        }
+        Some(self.tx_offsets[block as usize] + tx_in_block as u64)
+    }
+
+    // Reverse of global_tx_index
+    pub fn transaction_by_global_index(&self, n: u64) -> Option<&Transaction> {
+        // Last block whose first transaction is at or before n
+        let block = self.tx_offsets.partition_point(|&offset| offset <= n).checked_sub(1)?;
+        self.chain[block].transactions.get((n - self.tx_offsets[block]) as usize)
+    }
+
+    // Coins created so far. Reward transactions also carry the block's fees,
+    // which already existed, so those are left out.
+    pub fn total_issuance(&self) -> f64 {
+        self.chain.iter()
+            .flat_map(|block| &block.transactions)
+            .map(issued)
+            .sum()
+    }
+
+    // Check every mined block paid out exactly the reward policy's amount
+    // for its height plus its fees, across all its reward transactions.
+    // Blocks mined before the current policy was set are held to it too.
+    pub fn audit_issuance(&self) -> Result<(), Vec<IssuanceError>> {
+        let errors: Vec<IssuanceError> = self.chain.iter()
+            .skip(1)
+            .filter_map(|block| {
+                let (expected, got) = self.reward_mismatch(block)?;
+                Some(IssuanceError::WrongReward { index: block.index, hash: block.hash, expected, got })
+            })
+            .collect();
+
+        if errors.is_empty() { Ok(()) } else { Err(errors) }
+    }
+
+    // Net change to each address caused by block `index` (empty if there is
+    // no such block). As in balances, System is only charged what the block
+    // created, so the deltas add up to 0.
+    pub fn balance_deltas(&self, index: u64) -> HashMap<String, f64> {
+        let mut deltas = HashMap::new();
+        let Some(block) = self.chain.get(index as usize) else {
+            return deltas;
+        };
+
+        for tx in &block.transactions {
+            apply_transaction(&mut deltas, tx);
+        }
+        if let Some(system) = deltas.get_mut(SYSTEM_ADDRESS) {
+            *system = -block.transactions.iter().map(issued).sum::<f64>();
+        }
+        deltas
+    }
+
+    // The `n` largest account_balances, highest first (ties by address)
+    pub fn top_balances(&self, n: usize) -> Vec<(String, f64)> {
+        let mut balances: Vec<(String, f64)> = self.account_balances()
+            .map(|(address, balance)| (address.to_string(), balance))
+            .collect();
+        balances.sort_by(|a, b| b.1.total_cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        balances.truncate(n);
+        balances
+    }
+}
+
+// Contribution of `tx` to total_issuance
+fn issued(tx: &Transaction) -> f64 {
+    if tx.is_system() { tx.amount } else { -tx.fee }
+}
+
+// Change `block` makes to the balance of `address`, every reward counted.
+// The fees in System's reward payouts were paid by the senders, not minted.
+fn balance_change(block: &Block, address: &str) -> f64 {
+    if address == SYSTEM_ADDRESS {
+        return -block.transactions.iter().map(issued).sum::<f64>();
+    }
+    block.balance_delta(address, true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::block::Block;
+    use crate::test_util::TestChainBuilder;
+    use crate::transaction::{Transaction, SYSTEM_ADDRESS};
+
+    #[test]
+    fn balance_at_an_earlier_height() {
+        let mut chain = Blockchain::new_for_test();
+        chain.mine_pending_transactions("Alice".to_string());
+        chain.add_transaction(Transaction::new("Alice".to_string(), "Bob".to_string(), 30.0)).unwrap();
+        chain.mine_pending_transactions("Miner".to_string());
+
+        assert_eq!(chain.balance_at("Alice", 0), 0.0);
+        assert_eq!(chain.balance_at("Alice", 1), 100.0);
+        assert_eq!(chain.balance_at("Alice", 2), 70.0);
+        assert_eq!(chain.balance_at("Bob", 1), 0.0);
+        assert_eq!(Ok(chain.balance_at("Bob", u64::MAX)), chain.get_balance("Bob"));
+    }
+
+    #[test]
+    fn overspend_shows_up_as_a_negative_balance() {
+        let mut chain = Blockchain::new_for_test();
+        chain.mine_pending_transactions("Alice".to_string());
+        // Bob never had anything; add_transaction would refuse this, so it
+        // goes straight onto the chain the way a hand-edited file would
+        let overspend = Transaction::new("Bob".to_string(), "Carol".to_string(), 40.0);
+        let latest = chain.get_latest_block();
+        let block = Block::new(latest.index + 1, vec![overspend], latest.hash, 0);
+        chain.chain.push(block);
+        chain.reindex();
+
+        assert_eq!(chain.find_negative_balances(), vec![("Bob".to_string(), -40.0)]);
+    }
+
+    // Queue a transfer with the sender's next nonce
+    fn send(chain: &mut Blockchain, from: &str, to: &str, amount: f64) {
+        let tx = Transaction::new(from.to_string(), to.to_string(), amount).with_nonce(chain.next_nonce(from));
+        chain.add_transaction(tx).unwrap();
+    }
+
+    #[test]
+    fn flow_between_counts_each_direction() {
+        let mut chain = Blockchain::new_for_test();
+        chain.mine_pending_transactions("Alice".to_string());
+        chain.mine_pending_transactions("Bob".to_string());
+        send(&mut chain, "Alice", "Bob", 30.0);
+        send(&mut chain, "Bob", "Alice", 5.0);
+        send(&mut chain, "Alice", "Carol", 50.0);
+        chain.mine_pending_transactions("Miner".to_string());
+        send(&mut chain, "Alice", "Bob", 2.5);
+        chain.mine_pending_transactions("Miner".to_string());
+
+        assert_eq!(chain.flow_between("Alice", "Bob"), (32.5, 5.0));
+        assert_eq!(chain.flow_between("Bob", "Alice"), (5.0, 32.5));
+        assert_eq!(chain.flow_between("Bob", "Carol"), (0.0, 0.0));
+    }
+
+    #[test]
+    fn transactions_per_block_stats() {
+        let mut chain = Blockchain::new_for_test();
+        assert_eq!(chain.avg_transactions_per_block(true), 0.0);
+
+        chain.mine_pending_transactions("Alice".to_string());
+        send(&mut chain, "Alice", "Bob", 1.0);
+        send(&mut chain, "Alice", "Carol", 1.0);
+        send(&mut chain, "Alice", "Dave", 1.0);
+        chain.mine_pending_transactions("Miner".to_string());
+        send(&mut chain, "Bob", "Carol", 0.5);
+        chain.mine_pending_transactions("Miner".to_string());
+
+        assert_eq!(chain.avg_transactions_per_block(true), 7.0 / 3.0);
+        assert_eq!(chain.avg_transactions_per_block(false), 4.0 / 3.0);
+        assert_eq!(chain.max_transactions_in_block(), 4);
+    }
+
+    #[test]
+    fn block_times_between_stamped_blocks() {
+        let mut chain = Blockchain::new_for_test();
+        assert_eq!(chain.avg_block_time(), 0.0);
+
+        for _ in 0..3 {
+            chain.mine_pending_transactions("Alice".to_string());
+        }
+        // Only the stamps matter here, so spread them out by hand
+        for (i, timestamp) in [1_000, 1_060, 1_090, 1_180].into_iter().enumerate() {
+            chain.chain[i].timestamp = timestamp;
+        }
+
+        assert_eq!(chain.block_times(), vec![60, 30, 90]);
+        assert_eq!(chain.avg_block_time(), 60.0);
+    }
+
+    #[test]
+    fn global_transaction_numbering_is_contiguous() {
+        let mut chain = Blockchain::new_for_test();
+        chain.mine_pending_transactions("Alice".to_string());
+        send(&mut chain, "Alice", "Bob", 1.0);
+        send(&mut chain, "Alice", "Carol", 1.0);
+        chain.mine_pending_transactions("Miner".to_string());
+        chain.mine_pending_transactions("Miner".to_string());
+
+        let mut n = 0;
+        for block in chain.blocks() {
+            for (i, tx) in block.transactions().iter().enumerate() {
+                assert_eq!(chain.global_tx_index(block.index(), i), Some(n));
+                assert_eq!(chain.transaction_by_global_index(n).unwrap().id(), tx.id());
+                n += 1;
+            }
+        }
+        assert_eq!(chain.total_transactions(), n);
+        assert_eq!(chain.transaction_by_global_index(n).map(Transaction::id), None);
+        assert_eq!(chain.global_tx_index(1, 1), None);
+        assert_eq!(chain.global_tx_index(9, 0), None);
+    }
+
+    #[test]
+    fn top_balances_highest_first() {
+        let mut chain = Blockchain::new_for_test();
+        chain.mine_pending_transactions("Alice".to_string());
+        chain.mine_pending_transactions("Bob".to_string());
+        send(&mut chain, "Alice", "Carol", 40.0);
+        send(&mut chain, "Bob", "Dave", 60.0);
+        chain.mine_pending_transactions("Erin".to_string());
+
+        assert_eq!(chain.top_balances(3), vec![
+            ("Erin".to_string(), 100.0),
+            ("Alice".to_string(), 60.0),
+            ("Dave".to_string(), 60.0),
+        ]);
+        assert_eq!(chain.top_balances(10).len(), 5);
+        assert!(chain.top_balances(10).iter().all(|(address, _)| address != SYSTEM_ADDRESS && address != "Genesis"));
+    }
+
+    #[test]
+    fn tx_counts_match_every_block() {
+        let mut chain = Blockchain::new_for_test();
+        chain.mine_pending_transactions("Alice".to_string());
+        send(&mut chain, "Alice", "Bob", 1.0);
+        send(&mut chain, "Alice", "Carol", 1.0);
+        chain.mine_pending_transactions("Miner".to_string());
+
+        assert_eq!(chain.tx_counts(), vec![1, 1, 3]);
+        let counts: Vec<usize> = chain.blocks().iter().map(Block::transaction_count).collect();
+        assert_eq!(chain.tx_counts(), counts);
+        assert_eq!(chain.total_transactions(), 5);
+    }
+
+    #[test]
+    fn stalled_once_the_threshold_is_crossed() {
+        let mut chain = Blockchain::new_for_test();
+        chain.mine_pending_transactions("Alice".to_string());
+        let tip = chain.get_latest_block().timestamp();
+
+        assert_eq!(chain.time_since_last_block(tip + 90), 90);
+        assert!(!chain.is_stalled(tip + 300, 300));
+        assert!(chain.is_stalled(tip + 301, 300));
+    }
+
+    #[test]
+    fn transactions_in_range_is_inclusive() {
+        let mut chain = Blockchain::new_for_test();
+        chain.mine_pending_transactions("Alice".to_string());
+        send(&mut chain, "Alice", "Bob", 1.0);
+        chain.mine_pending_transactions("Miner".to_string());
+        send(&mut chain, "Alice", "Carol", 1.0);
+        chain.mine_pending_transactions("Miner".to_string());
+
+        // Restamp so the range is exact. Carol's transfer keeps the time it
+        // was made, a block before the one that mined it.
+        let start = 1_700_000_000;
+        let stamps = [vec![start], vec![start + 60], vec![start + 120, start + 120], vec![start + 120, start + 180]];
+        for (block, stamps) in chain.chain.iter_mut().zip(stamps) {
+            for (tx, timestamp) in block.transactions.iter_mut().zip(stamps) {
+                tx.timestamp = timestamp;
+            }
+        }
+
+        let found: Vec<(u64, &str)> = chain.transactions_in_range(start + 60, start + 120)
+            .into_iter()
+            .map(|(index, tx)| (index, tx.receiver()))
+            .collect();
+        assert_eq!(found, vec![(1, "Alice"), (2, "Bob"), (2, "Miner"), (3, "Carol")]);
+        assert!(chain.transactions_in_range(start + 1, start + 59).is_empty());
+    }
+
+    #[test]
+    fn block_luck_from_the_stored_nonce_and_difficulty() {
+        let mut chain = Blockchain::new_for_test();
+        chain.mine_pending_transactions("Alice".to_string());
+
+        chain.chain[1].nonce = 0;
+        chain.chain[1].difficulty = 0;
+        assert_eq!(chain.block_luck(1), Some(1.0));
+        chain.chain[1].nonce = 7;
+        chain.chain[1].difficulty = 1;
+        assert_eq!(chain.block_luck(1), Some(0.5));
+        chain.chain[1].nonce = 511;
+        chain.chain[1].difficulty = 2;
+        assert_eq!(chain.block_luck(1), Some(2.0));
+        assert_eq!(chain.block_luck(2), None);
+    }
+
+    #[test]
+    fn balance_deltas_add_up_to_zero() {
+        let mut chain = Blockchain::new_for_test();
+        chain.mine_pending_transactions("Alice".to_string());
+        chain.mine_pending_transactions("Bob".to_string());
+        send(&mut chain, "Alice", "Bob", 30.0);
+        send(&mut chain, "Alice", "Carol", 20.0);
+        send(&mut chain, "Bob", "Carol", 5.0);
+        chain.mine_pending_transactions("Miner".to_string());
+
+        let deltas = chain.balance_deltas(3);
+        assert_eq!(deltas["Alice"], -50.0);
+        assert_eq!(deltas["Bob"], 25.0);
+        assert_eq!(deltas["Carol"], 25.0);
+        assert_eq!(deltas["Miner"], 100.0);
+        assert_eq!(deltas[SYSTEM_ADDRESS], -100.0);
+        assert_eq!(deltas.values().sum::<f64>(), 0.0);
+        assert!(chain.balance_deltas(4).is_empty());
+    }
+
+    #[test]
+    fn longest_streak_finds_three_in_a_row() {
+        let mut chain = Blockchain::new_for_test();
+        assert_eq!(chain.longest_miner_streak(), (String::new(), 0));
+
+        for miner in ["Alice", "Bob", "Bob", "Bob", "Alice", "Alice"] {
+            chain.mine_pending_transactions(miner.to_string());
+        }
+        assert_eq!(chain.longest_miner_streak(), ("Bob".to_string(), 3));
+    }
+
+    #[test]
+    fn miner_earnings_split_rewards_from_fees() {
+        let mut chain = TestChainBuilder::new().mine("Alice").build();
+        let paying = Transaction::new("Alice".to_string(), "Bob".to_string(), 10.0).with_fee(2.5);
+        chain.add_transaction(paying).unwrap();
+        chain.mine_pending_transactions("Miner".to_string());
+        let paying = Transaction::new("Alice".to_string(), "Bob".to_string(), 10.0).with_fee(1.5).with_nonce(1);
+        chain.add_transaction(paying).unwrap();
+        chain.mine_pending_transactions("Miner".to_string());
+
+        assert_eq!(chain.miner_earnings("Miner"), (200.0, 4.0));
+        assert_eq!(chain.miner_earnings("Alice"), (100.0, 0.0));
+        assert_eq!(chain.miner_earnings("Bob"), (0.0, 0.0));
+    }
+
+    #[test]
+    fn tps_counts_transactions_inside_the_window() {
+        let chain = TestChainBuilder::new()
+            .mine("Alice")
+            .tx("Alice", "Bob", 10.0)
+            .tx("Alice", "Carol", 10.0)
+            .mine("Miner")
+            .tx("Bob", "Carol", 5.0)
+            .mine("Miner")
+            .build();
+        // Blocks are stamped 60 seconds apart
+        let now = chain.get_latest_block().timestamp();
+
+        assert_eq!(chain.tps(60, now), 1.0 / 60.0);
+        assert_eq!(chain.tps(120, now), 3.0 / 120.0);
+        assert_eq!(chain.tps(60, now - 60), 2.0 / 60.0);
+        assert_eq!(chain.tps(60, now + 60), 0.0);
+        assert_eq!(chain.tps(0, now), 0.0);
+    }
+
+    #[test]
+    fn skipped_index_is_reported_as_a_gap() {
+        let mut chain = TestChainBuilder::new().mine("Alice").mine("Alice").mine("Alice").mine("Alice").build();
+        assert!(chain.find_index_gaps().is_empty());
+
+        // As if the shard holding blocks 1 and 3 never arrived
+        chain.chain.remove(3);
+        chain.chain.remove(1);
+        assert_eq!(chain.find_index_gaps(), vec![1, 3]);
+    }
+
+    #[test]
+    fn fee_totals_per_block_and_overall() {
+        let mut chain = TestChainBuilder::new().mine("Alice").build();
+        chain.add_transaction(Transaction::new("Alice".to_string(), "Bob".to_string(), 10.0).with_fee(0.5)).unwrap();
+        chain.add_transaction(Transaction::new("Alice".to_string(), "Carol".to_string(), 10.0).with_fee(1.25).with_nonce(1)).unwrap();
+        chain.mine_pending_transactions("Miner".to_string());
+        chain.mine_pending_transactions("Miner".to_string());
+        chain.add_transaction(Transaction::new("Bob".to_string(), "Carol".to_string(), 5.0).with_fee(2.0)).unwrap();
+        chain.mine_pending_transactions("Miner".to_string());
+
+        assert_eq!(chain.fees_in_block(1), Some(0.0));
+        assert_eq!(chain.fees_in_block(2), Some(1.75));
+        assert_eq!(chain.fees_in_block(3), Some(0.0));
+        assert_eq!(chain.fees_in_block(4), Some(2.0));
+        assert_eq!(chain.fees_in_block(5), None);
+        assert_eq!(chain.total_fees(), 3.75);
+    }
+
+    #[test]
+    fn audit_catches_an_inflated_coinbase() {
+        let mut chain = TestChainBuilder::new()
+            .mine("Alice")
+            .tx("Alice", "Bob", 30.0)
+            .mine("Miner")
+            .mine("Miner")
+            .build();
+        assert_eq!(chain.audit_issuance(), Ok(()));
+
+        chain.tamper_transaction(2, 1, 500.0).unwrap();
+        let hash = chain.blocks()[2].hash;
+        assert_eq!(
+            chain.audit_issuance(),
+            Err(vec![IssuanceError::WrongReward { index: 2, hash, expected: 100.0, got: 500.0 }])
+        );
+    }
+
+    #[test]
+    fn balance_history_matches_balance_at() {
+        let chain = TestChainBuilder::new()
+            .mine("Alice")
+            .mine("Bob")
+            .tx("Alice", "Carol", 30.0)
+            .mine("Miner")
+            .tx("Carol", "Alice", 5.0)
+            .mine("Miner")
+            .mine("Alice")
+            .build();
+
+        let history = chain.balance_history("Alice");
+        assert_eq!(history, vec![(1, 100.0), (3, 70.0), (4, 75.0), (5, 175.0)]);
+        for (index, balance) in history {
+            assert_eq!(chain.balance_at("Alice", index), balance);
+        }
+        assert!(chain.balance_history("Dave").is_empty());
+    }
+
+    #[test]
+    fn system_history_leaves_the_fees_out() {
+        let mut chain = TestChainBuilder::new().mine("Alice").build();
+        chain.add_transaction(Transaction::new("Alice".to_string(), "Bob".to_string(), 30.0).with_fee(5.0)).unwrap();
+        chain.mine_pending_transactions("Miner".to_string());
+
+        assert_eq!(chain.balance_history(SYSTEM_ADDRESS), vec![(1, -100.0), (2, -200.0)]);
+        assert_eq!(chain.balance_at(SYSTEM_ADDRESS, 2), -200.0);
+        assert_eq!(Ok(chain.balance_at(SYSTEM_ADDRESS, 2)), chain.get_balance(SYSTEM_ADDRESS));
+        assert_eq!(chain.balance_history("Alice"), vec![(1, 100.0), (2, 65.0)]);
+        assert_eq!(chain.balance_history("Miner"), vec![(2, 105.0)]);
+    }
+
+    #[test]
+    fn top_balances_follow_new_and_reloaded_blocks() {
+        let mut chain = TestChainBuilder::new().mine("Alice").tx("Alice", "Bob", 60.0).build();
+        assert_eq!(chain.top_balances(1), vec![("Alice".to_string(), 100.0)]);
+
+        chain.mine_pending_transactions("Carol".to_string());
+        assert_eq!(chain.top_balances(2), vec![("Carol".to_string(), 100.0), ("Bob".to_string(), 60.0)]);
+
+        let reloaded = Blockchain::from_hex(&chain.to_hex()).unwrap();
+        assert_eq!(reloaded.top_balances(10), chain.top_balances(10));
+    }
+}
@@ -0,0 +1,392 @@
+use sha2::{Sha256, Digest};
+use serde::{Serialize, Deserialize};
+use chrono::Utc;
+use colored::*;
+use std::fmt;
+use std::io;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+
+use crate::hash::{Hash, HashFormat};
+use crate::merkle;
+use crate::transaction::{push_field, Transaction};
+
+// Block structure
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Block {
+    pub(crate) index: u64,
+    pub(crate) timestamp: i64,
+    pub(crate) transactions: Vec<Transaction>,
+    pub(crate) previous_hash: Hash,
+    pub(crate) hash: Hash,
+    pub(crate) nonce: u64,
+    pub(crate) difficulty: usize,
+}
+
+impl Block {
+    pub fn new(index: u64, transactions: Vec<Transaction>, previous_hash: Hash, difficulty: usize) -> Self {
+        let timestamp = Utc::now().timestamp();
+        let mut block = Self {
+            index,
+            timestamp,
+            transactions,
+            previous_hash,
+            hash: Hash::ZERO,
+            nonce: 0,
+            difficulty,
+        };
+        block.hash = block.calculate_hash();
+        block
+    }
+
+    pub fn calculate_hash(&self) -> Hash {
+        let mut hasher = Sha256::new();
+        hasher.update(self.hash_preimage());
+        Hash::from_bytes(hasher.finalize().into())
+    }
+
+    // The exact bytes calculate_hash feeds to SHA-256, for tracking down why
+    // two implementations disagree on a hash
+    pub fn hash_preimage(&self) -> Vec<u8> {
+        // Variable-length fields are length-prefixed and each transaction
+        // contributes its fixed-length hash, so no two blocks share a pre-image
+        let mut block_data = String::new();
+        push_field(&mut block_data, &self.index.to_string());
+        push_field(&mut block_data, &self.timestamp.to_string());
+        push_field(&mut block_data, &self.transactions.len().to_string());
+        for tx in &self.transactions {
+            block_data.push_str(&merkle::transaction_hash(tx));
+        }
+        // The genesis parent has always been hashed as "0", keep it that way
+        // so existing genesis hashes still verify
+        if self.previous_hash == Hash::ZERO {
+            push_field(&mut block_data, "0");
+        } else {
+            push_field(&mut block_data, &self.previous_hash.to_string());
+        }
+        push_field(&mut block_data, &self.nonce.to_string());
+        block_data.into_bytes()
+    }
+
+    pub fn index(&self) -> u64 {
+        self.index
+    }
+
+    pub fn timestamp(&self) -> i64 {
+        self.timestamp
+    }
+
+    pub fn transactions(&self) -> &[Transaction] {
+        &self.transactions
+    }
+
+    pub fn transaction_count(&self) -> usize {
+        self.transactions.len()
+    }
+
+    pub fn previous_hash(&self) -> Hash {
+        self.previous_hash
+    }
+
+    pub fn hash(&self) -> Hash {
+        self.hash
+    }
+
+    pub fn nonce(&self) -> u64 {
+        self.nonce
+    }
+
+    pub fn difficulty(&self) -> usize {
+        self.difficulty
+    }
+
+    // First and last 8 hex characters of the hash, for compact listings
+    pub fn short_hash(&self) -> String {
+        self.short_hash_in(HashFormat::Hex)
+    }
+
+    pub fn short_hash_in(&self, format: HashFormat) -> String {
+        let hash = self.hash.to_string_in(format);
+        format!("{}…{}", &hash[..8], &hash[hash.len() - 8..])
+    }
+
+    // The Display output with hashes written in `format`
+    pub fn render(&self, format: HashFormat) -> String {
+        let mut out = String::new();
+        // Writing into a String can't fail
+        self.write_to(&mut out, format).unwrap();
+        out
+    }
+
+    // Combined size of the block's transactions, see Transaction::size
+    pub fn size(&self) -> usize {
+        self.transactions.iter().map(Transaction::size).sum()
+    }
+
+    pub fn merkle_root(&self) -> String {
+        merkle::merkle_root(&self.transactions)
+    }
+
+    // Net change this block makes to the balance of `address`: what it
+    // receives minus what it sends, fees included. With `rewards` false,
+    // reward payouts to it are left out.
+    pub(crate) fn balance_delta(&self, address: &str, rewards: bool) -> f64 {
+        let mut delta = 0.0;
+        for tx in &self.transactions {
+            if tx.sender == address {
+                delta -= tx.total_cost();
+            }
+            if rewards || !tx.is_system() {
+                delta += tx.received_by(address);
+            }
+        }
+        delta
+    }
+
+    // Receiver of the block's reward transaction (of the last share when the
+    // reward was split). For the genesis block this is "Genesis".
+    pub fn miner(&self) -> Option<&str> {
+        self.transactions.iter()
+            .rev()
+            .find(|tx| tx.is_system())
+            .map(|tx| tx.receiver.as_str())
+    }
+
+    // Message the miner embedded in this block's reward transaction, if any
+    pub fn coinbase_message(&self) -> Option<&str> {
+        self.transactions.iter()
+            .rev()
+            .find(|tx| tx.is_system())
+            .and_then(|tx| tx.memo.as_deref())
+    }
+
+    // Whether the stored hash has the leading zeros the block's difficulty
+    // asks for. Difficulty 0 means no proof of work at all (e.g. test chains),
+    // so any hash passes.
+    pub fn meets_difficulty(&self) -> bool {
+        if self.difficulty == 0 {
+            return true;
+        }
+        self.hash.leading_zero_digits() >= self.difficulty
+    }
+
+    pub fn mine_block(&mut self) {
+        println!("\n{}", "⛏️  Mining block...".bright_yellow().bold());
+        print!("Nonce: ");
+
+        while !self.meets_difficulty() {
+            self.nonce += 1;
+            self.hash = self.calculate_hash();
+
+            // Show progress every 10000 attempts
+            if self.nonce.is_multiple_of(10000) {
+                show_progress(&mut std::io::stdout(), self.nonce);
+            }
+        }
+
+        self.print_mined();
+    }
+
+    // Same as mine_block, but searches nonces on `threads` threads at once.
+    // Thread t tries t+1, t+1+threads, t+1+2*threads, ...
+    pub fn mine_block_parallel(&mut self, threads: usize) {
+        if threads <= 1 {
+            return self.mine_block();
+        }
+
+        println!("\n{}", "⛏️  Mining block...".bright_yellow().bold());
+        println!("Threads: {}", threads.to_string().bright_cyan());
+
+        if !self.meets_difficulty() {
+            let found = AtomicBool::new(false);
+            let template = &*self;
+
+            let solution = thread::scope(|scope| {
+                let workers: Vec<_> = (0..threads)
+                    .map(|t| {
+                        let found = &found;
+                        let mut candidate = template.clone();
+                        scope.spawn(move || {
+                            let mut nonce = t as u64 + 1;
+                            while !found.load(Ordering::Relaxed) {
+                                candidate.nonce = nonce;
+                                candidate.hash = candidate.calculate_hash();
+                                if candidate.meets_difficulty() {
+                                    found.store(true, Ordering::Relaxed);
+                                    return Some((nonce, candidate.hash));
+                                }
+                                nonce += threads as u64;
+                            }
+                            None
+                        })
+                    })
+                    .collect();
+
+                // Several threads may succeed at once; keep the lowest nonce
+                workers.into_iter()
+                    .filter_map(|worker| worker.join().unwrap())
+                    .min_by_key(|(nonce, _)| *nonce)
+            });
+
+            let (nonce, hash) = solution.unwrap();
+            self.nonce = nonce;
+            self.hash = hash;
+        }
+
+        self.print_mined();
+    }
+
+    fn print_mined(&self) {
+        println!("\n{} Block mined! Hash: {}",
+            "✓".bright_green().bold(),
+            self.hash.to_string().bright_green()
+        );
+        println!("Nonce found: {}", self.nonce.to_string().bright_cyan().bold());
+    }
+}
+
+// Progress output is best effort, a closed pipe mustn't stop mining
+fn show_progress<W: io::Write>(out: &mut W, nonce: u64) {
+    let _ = write!(out, "{} ", nonce.to_string().bright_cyan());
+    let _ = out.flush();
+}
+
+impl fmt::Display for Block {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.write_to(f, HashFormat::Hex)
+    }
+}
+
+impl Block {
+    fn write_to<W: fmt::Write>(&self, f: &mut W, format: HashFormat) -> fmt::Result {
+        writeln!(f, "\n{}", "═".repeat(80).bright_blue())?;
+        writeln!(f, "{} {}", "Block #".bright_white().bold(), self.index.to_string().bright_cyan().bold())?;
+        writeln!(f, "{}", "─".repeat(80).bright_blue())?;
+        writeln!(f, "{}: {}", "Timestamp".bright_white(), self.timestamp)?;
+        writeln!(f, "{}: {}", "Previous Hash".bright_white(), self.previous_hash.to_string_in(format).bright_yellow())?;
+        writeln!(f, "{}: {}", "Hash".bright_white(), self.hash.to_string_in(format).bright_green())?;
+        writeln!(f, "{}: {}", "Nonce".bright_white(), self.nonce.to_string().bright_cyan())?;
+        writeln!(f, "{}: {}", "Difficulty".bright_white(), self.difficulty)?;
+        writeln!(f, "\n{}", "Transactions:".bright_white().bold())?;
+
+        for (i, tx) in self.transactions.iter().enumerate() {
+            // A batch payout gets one line per receiver under the same number
+            let number = format!("{}.", i + 1);
+            for (j, (receiver, amount)) in tx.outputs().enumerate() {
+                writeln!(f, "  {:width$} {} {} → {} {} coins",
+                    if j == 0 { number.as_str() } else { "" },
+                    tx.sender.bright_magenta(),
+                    "→".bright_white(),
+                    receiver.bright_magenta(),
+                    amount.to_string().bright_yellow(),
+                    width = number.len()
+                )?;
+            }
+            if tx.fee > 0.0 {
+                writeln!(f, "     fee: {}", tx.fee.to_string().bright_yellow())?;
+            }
+        }
+        writeln!(f, "{}", "═".repeat(80).bright_blue())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transaction::SYSTEM_ADDRESS;
+
+    #[test]
+    fn shifted_field_boundaries_hash_differently() {
+        // Index 1 at time 12 and index 11 at time 2 used to concatenate to
+        // the same "112"
+        let mut first = Block::new(1, Vec::new(), Hash::ZERO, 0);
+        let mut second = Block::new(11, Vec::new(), Hash::ZERO, 0);
+        first.timestamp = 12;
+        second.timestamp = 2;
+
+        assert_ne!(first.calculate_hash(), second.calculate_hash());
+    }
+
+    #[test]
+    fn size_sums_the_transaction_sizes() {
+        let transactions = vec![
+            Transaction::new("Alice".to_string(), "Bob".to_string(), 1.0),
+            Transaction::new("Bob".to_string(), "Carol".to_string(), 2.0).with_memo("thanks".to_string()),
+        ];
+        let expected = transactions[0].size() + transactions[1].size();
+
+        assert_eq!(Block::new(1, transactions, Hash::ZERO, 0).size(), expected);
+    }
+
+    #[test]
+    fn short_hash_keeps_both_ends_of_the_hash() {
+        let block = Block::new(1, Vec::new(), Hash::ZERO, 0);
+        let full = block.hash.to_string();
+
+        assert_eq!(block.short_hash(), format!("{}…{}", &full[..8], &full[56..]));
+    }
+
+    // Refuses every write and flush, like stdout piped into a closed `head`
+    struct BrokenPipe;
+
+    impl io::Write for BrokenPipe {
+        fn write(&mut self, _buf: &[u8]) -> io::Result<usize> {
+            Err(io::ErrorKind::BrokenPipe.into())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Err(io::ErrorKind::BrokenPipe.into())
+        }
+    }
+
+    #[test]
+    fn progress_to_a_broken_pipe_does_not_panic() {
+        show_progress(&mut BrokenPipe, 10_000);
+    }
+
+    #[test]
+    fn difficulty_zero_needs_no_work() {
+        let mut block = Block::new(1, Vec::new(), Hash::ZERO, 0);
+        block.hash = "f".repeat(64).parse().unwrap();
+        assert!(block.meets_difficulty());
+
+        block.difficulty = 1;
+        assert!(!block.meets_difficulty());
+    }
+
+    #[test]
+    fn hash_is_sha256_of_the_preimage() {
+        let tx = Transaction::new("Alice".to_string(), "Bob".to_string(), 1.0).with_memo("lunch".to_string());
+        let block = Block::new(3, vec![tx.clone()], Hash::ZERO, 0);
+
+        let digest: [u8; 32] = Sha256::digest(block.hash_preimage()).into();
+        assert_eq!(Hash::from_bytes(digest), block.calculate_hash());
+        assert_eq!(format!("{:x}", Sha256::digest(tx.hash_preimage())), tx.id());
+    }
+
+    #[test]
+    fn render_writes_hashes_in_the_chosen_format() {
+        let block = Block::new(1, Vec::new(), Hash::ZERO, 0);
+
+        for format in [HashFormat::Hex, HashFormat::Base64] {
+            let rendered = block.render(format);
+            assert!(rendered.contains(&block.hash.to_string_in(format)));
+        }
+        assert_eq!(block.render(HashFormat::Hex), block.to_string());
+    }
+
+    #[test]
+    fn balance_delta_counts_fees_and_optionally_rewards() {
+        let transactions = vec![
+            Transaction::new("Alice".to_string(), "Bob".to_string(), 10.0).with_fee(0.5),
+            Transaction::new("Bob".to_string(), "Alice".to_string(), 2.0),
+            Transaction::new(SYSTEM_ADDRESS.to_string(), "Alice".to_string(), 100.5),
+        ];
+        let block = Block::new(1, transactions, Hash::ZERO, 0);
+
+        assert_eq!(block.balance_delta("Alice", true), 92.0);
+        assert_eq!(block.balance_delta("Alice", false), -8.5);
+        assert_eq!(block.balance_delta("Bob", true), 8.0);
+        assert_eq!(block.balance_delta("Carol", true), 0.0);
+    }
+}
@@ -0,0 +1,112 @@
+use sha2::{Sha256, Digest};
+
+use crate::block::Block;
+use crate::blockchain::Blockchain;
+
+// Bloom filter over the addresses a block touches. A miss means the block
+// definitely doesn't involve the address; a hit only means it might.
+
+const FILTER_BITS: usize = 512;
+const FILTER_HASHES: u64 = 3;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BloomFilter {
+    bits: [u64; FILTER_BITS / 64],
+}
+
+impl BloomFilter {
+    pub fn new() -> Self {
+        Self { bits: [0; FILTER_BITS / 64] }
+    }
+
+    // Every sender and receiver in the block
+    pub fn for_block(block: &Block) -> Self {
+        let mut filter = Self::new();
+        for tx in &block.transactions {
+            filter.insert(&tx.sender);
+            for (receiver, _) in tx.outputs() {
+                filter.insert(receiver);
+            }
+        }
+        filter
+    }
+
+    // Bit positions for `item`, by double hashing two halves of its SHA-256
+    fn positions(item: &str) -> impl Iterator<Item = usize> {
+        let digest = Sha256::digest(item.as_bytes());
+        let h1 = u64::from_le_bytes(digest[0..8].try_into().unwrap());
+        let h2 = u64::from_le_bytes(digest[8..16].try_into().unwrap());
+        (0..FILTER_HASHES).map(move |i| (h1.wrapping_add(i.wrapping_mul(h2)) % FILTER_BITS as u64) as usize)
+    }
+
+    pub fn insert(&mut self, item: &str) {
+        for bit in Self::positions(item) {
+            self.bits[bit / 64] |= 1 << (bit % 64);
+        }
+    }
+
+    pub fn might_contain(&self, item: &str) -> bool {
+        Self::positions(item).all(|bit| self.bits[bit / 64] & (1 << (bit % 64)) != 0)
+    }
+}
+
+impl Default for BloomFilter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Blockchain {
+    pub fn block_filter(&self, index: u64) -> Option<&BloomFilter> {
+        self.block_filters.get(index as usize)
+    }
+
+    // Indices of blocks whose filter matches any of `addrs`. Every block
+    // touching one of them is included, along with the odd false positive.
+    pub fn blocks_matching(&self, addrs: &[String]) -> Vec<u64> {
+        self.block_filters.iter()
+            .enumerate()
+            .filter(|(_, filter)| addrs.iter().any(|address| filter.might_contain(address)))
+            .map(|(index, _)| index as u64)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blockchain::Blockchain;
+    use crate::transaction::Transaction;
+
+    #[test]
+    fn filter_has_no_false_negatives() {
+        let mut filter = BloomFilter::new();
+        let addresses: Vec<String> = (0..50).map(|i| format!("address{}", i)).collect();
+        for address in &addresses {
+            filter.insert(address);
+        }
+
+        assert!(addresses.iter().all(|address| filter.might_contain(address)));
+        assert!(!BloomFilter::new().might_contain("address0"));
+    }
+
+    #[test]
+    fn blocks_matching_finds_every_block_touching_an_address() {
+        let mut chain = Blockchain::new_for_test();
+        chain.mine_pending_transactions("Alice".to_string());
+        chain.mine_pending_transactions("Miner".to_string());
+        chain.add_transaction(Transaction::new("Alice".to_string(), "Bob".to_string(), 5.0)).unwrap();
+        chain.mine_pending_transactions("Miner".to_string());
+
+        let matches = chain.blocks_matching(&["Bob".to_string()]);
+        assert!(matches.contains(&3));
+        let matches = chain.blocks_matching(&["Alice".to_string(), "Nobody".to_string()]);
+        for block in chain.blocks() {
+            let touches = block.transactions().iter()
+                .any(|tx| tx.sender() == "Alice" || tx.receiver() == "Alice");
+            if touches {
+                assert!(matches.contains(&block.index()));
+            }
+        }
+    }
+}
@@ -0,0 +1,73 @@
+use serde::{Serialize, Deserialize};
+
+use crate::block::Block;
+use crate::blockchain::{Blockchain, MAX_DIFFICULTY};
+use crate::hash::Hash;
+
+// A block plus what's needed to check it without the rest of the chain
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BlockBundle {
+    pub block: Block,
+    pub parent_hash: Hash,
+    pub difficulty: usize,
+}
+
+impl Blockchain {
+    pub fn export_block_bundle(&self, index: u64) -> Option<BlockBundle> {
+        let block = self.chain.get(index as usize)?;
+        Some(BlockBundle {
+            block: block.clone(),
+            parent_hash: block.previous_hash,
+            difficulty: block.difficulty,
+        })
+    }
+}
+
+// Recompute the block's hash, check its proof of work at the stated
+// difficulty and that it points at the stated parent
+pub fn verify_block_bundle(bundle: &BlockBundle) -> bool {
+    let block = &bundle.block;
+    if bundle.difficulty > MAX_DIFFICULTY || block.difficulty != bundle.difficulty {
+        return false;
+    }
+    block.hash == block.calculate_hash()
+        && block.meets_difficulty()
+        && block.previous_hash == bundle.parent_hash
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bundle() -> BlockBundle {
+        let mut chain = Blockchain::new_for_test();
+        chain.set_difficulty(1).unwrap();
+        chain.mine_pending_transactions("Alice".to_string());
+        chain.export_block_bundle(1).unwrap()
+    }
+
+    #[test]
+    fn exported_bundle_verifies() {
+        let bundle = bundle();
+        assert_eq!(bundle.difficulty, 1);
+        assert!(verify_block_bundle(&bundle));
+    }
+
+    #[test]
+    fn tampered_transaction_fails() {
+        let mut bundle = bundle();
+        bundle.block.transactions[0].amount = 1_000.0;
+        assert!(!verify_block_bundle(&bundle));
+    }
+
+    #[test]
+    fn wrong_parent_or_difficulty_fails() {
+        let mut bundle = bundle();
+        bundle.parent_hash = bundle.block.hash;
+        assert!(!verify_block_bundle(&bundle));
+
+        let mut bundle = self::bundle();
+        bundle.difficulty = 0;
+        assert!(!verify_block_bundle(&bundle));
+    }
+}
@@ -0,0 +1,292 @@
+use std::fmt;
+use std::io;
+
+use crate::hash::Hash;
+
+// Errors raised while summing an address' balance
+#[derive(Debug, Clone, PartialEq)]
+pub enum BalanceError {
+    Overflow { address: String },
+    Underflow { address: String },
+}
+
+impl fmt::Display for BalanceError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BalanceError::Overflow { address } => write!(f, "balance of {} overflowed", address),
+            BalanceError::Underflow { address } => write!(f, "balance of {} underflowed", address),
+        }
+    }
+}
+
+impl std::error::Error for BalanceError {}
+
+// Reasons a transaction is refused by the pending pool
+#[derive(Debug, Clone, PartialEq)]
+pub enum TxError {
+    StaleNonce { sender: String, expected: u64, got: u64 },
+    DuplicateNonce { sender: String, nonce: u64 },
+    PoolFull { limit: usize },
+    InvalidAmount { amount: f64 },
+    InvalidFee { fee: f64 },
+    InsufficientBalance { sender: String, available: f64, amount: f64 },
+    SelfTransfer { address: String },
+    Dust { amount: f64, threshold: f64 },
+    WrongNetwork { expected: Option<String>, got: Option<String> },
+    SystemSender,
+}
+
+impl fmt::Display for TxError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TxError::StaleNonce { sender, expected, got } => {
+                write!(f, "nonce {} from {} is already used (next is {})", got, sender, expected)
+            }
+            TxError::DuplicateNonce { sender, nonce } => {
+                write!(f, "{} already has a pending transaction with nonce {}", sender, nonce)
+            }
+            TxError::PoolFull { limit } => write!(f, "pending pool is full ({} transactions)", limit),
+            TxError::InvalidAmount { amount } => write!(f, "invalid amount {}", amount),
+            TxError::InvalidFee { fee } => write!(f, "invalid fee {}", fee),
+            TxError::InsufficientBalance { sender, available, amount } => {
+                write!(f, "{} has {} available but tried to send {}", sender, available, amount)
+            }
+            TxError::SelfTransfer { address } => write!(f, "{} can't send coins to itself", address),
+            TxError::Dust { amount, threshold } => write!(f, "amount {} is below the dust threshold {}", amount, threshold),
+            TxError::WrongNetwork { expected, got } => write!(
+                f,
+                "transaction is for network {} but this chain is on {}",
+                got.as_deref().unwrap_or("(none)"),
+                expected.as_deref().unwrap_or("(none)")
+            ),
+            TxError::SystemSender => write!(f, "only the chain itself can send from System"),
+        }
+    }
+}
+
+impl std::error::Error for TxError {}
+
+// Errors raised while reading a saved chain
+#[derive(Debug)]
+pub enum LoadError {
+    Io(io::Error),
+    Parse(serde_json::Error),
+    UnsupportedVersion(u32),
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LoadError::Io(e) => write!(f, "could not read chain file: {}", e),
+            LoadError::Parse(e) => write!(f, "malformed chain file: {}", e),
+            LoadError::UnsupportedVersion(v) => write!(f, "unsupported chain file version {}", v),
+        }
+    }
+}
+
+impl std::error::Error for LoadError {}
+
+impl From<io::Error> for LoadError {
+    fn from(e: io::Error) -> Self {
+        LoadError::Io(e)
+    }
+}
+
+impl From<serde_json::Error> for LoadError {
+    fn from(e: serde_json::Error) -> Self {
+        LoadError::Parse(e)
+    }
+}
+
+// Reasons a block is rejected. `hash` is the hash the offending block
+// carries, which for InvalidHash is not the one its contents produce.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ValidationError {
+    InvalidHash { index: u64, hash: Hash },
+    InvalidPreviousHash { index: u64, hash: Hash },
+    InvalidProofOfWork { index: u64, hash: Hash },
+    InvalidIndex { expected: u64, got: u64, hash: Hash },
+    AlreadyHave { index: u64, hash: Hash },
+    DifficultyTooLow { index: u64, hash: Hash, required: usize, got: usize },
+    RuleViolation { index: u64, hash: Hash, tx_id: String, reason: String },
+    // A transaction the pending pool would have refused at that point
+    InvalidTransaction { index: u64, hash: Hash, tx_id: String, error: TxError },
+    WrongReward { index: u64, hash: Hash, expected: f64, got: f64 },
+    // Nonces from each sender must run 0, 1, 2, ... in chain order
+    WrongNonce { index: u64, hash: Hash, tx_id: String, expected: u64, got: u64 },
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ValidationError::InvalidHash { index, .. } => write!(f, "Block #{} has invalid hash", index),
+            ValidationError::InvalidPreviousHash { index, .. } => {
+                write!(f, "Block #{} has invalid previous hash", index)
+            }
+            ValidationError::InvalidProofOfWork { index, .. } => {
+                write!(f, "Block #{} has invalid proof of work", index)
+            }
+            ValidationError::InvalidIndex { expected, got, .. } => {
+                write!(f, "expected block #{} but got block #{}", expected, got)
+            }
+            ValidationError::AlreadyHave { index, .. } => write!(f, "Block #{} is already in the chain", index),
+            ValidationError::DifficultyTooLow { index, required, got, .. } => {
+                write!(f, "Block #{} was mined at difficulty {} but needs at least {}", index, got, required)
+            }
+            ValidationError::RuleViolation { index, tx_id, reason, .. } => {
+                write!(f, "transaction {} in block #{} breaks a rule: {}", tx_id, index, reason)
+            }
+            ValidationError::InvalidTransaction { index, tx_id, error, .. } => {
+                write!(f, "transaction {} in block #{} is invalid: {}", tx_id, index, error)
+            }
+            ValidationError::WrongReward { index, expected, got, .. } => {
+                write!(f, "Block #{} pays {} in rewards but is owed {}", index, got, expected)
+            }
+            ValidationError::WrongNonce { index, tx_id, expected, got, .. } => {
+                write!(f, "transaction {} in block #{} has nonce {} but {} was next", tx_id, index, got, expected)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ValidationError {}
+
+// Reasons a coinbase split is refused
+#[derive(Debug, Clone, PartialEq)]
+pub enum RewardSplitError {
+    NoRecipients,
+    InvalidShare { address: String, share: f64 },
+    SharesDontSumToOne { total: f64 },
+}
+
+impl fmt::Display for RewardSplitError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RewardSplitError::NoRecipients => write!(f, "reward split has no recipients"),
+            RewardSplitError::InvalidShare { address, share } => {
+                write!(f, "invalid reward share {} for {}", share, address)
+            }
+            RewardSplitError::SharesDontSumToOne { total } => {
+                write!(f, "reward shares add up to {} instead of 1", total)
+            }
+        }
+    }
+}
+
+impl std::error::Error for RewardSplitError {}
+
+// A block whose reward transactions don't add up to what it was owed
+#[derive(Debug, Clone, PartialEq)]
+pub enum IssuanceError {
+    WrongReward { index: u64, hash: Hash, expected: f64, got: f64 },
+}
+
+impl fmt::Display for IssuanceError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            IssuanceError::WrongReward { index, expected, got, .. } => {
+                write!(f, "Block #{} paid {} in rewards but was owed {}", index, got, expected)
+            }
+        }
+    }
+}
+
+impl std::error::Error for IssuanceError {}
+
+// Chain settings that were refused
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConfigError {
+    InvalidDifficulty { difficulty: usize },
+    BelowRequired { difficulty: usize, required: usize },
+    ChainStarted { height: u64 },
+    NetworkInUse,
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConfigError::InvalidDifficulty { difficulty } => {
+                write!(f, "difficulty {} is above the maximum of {}", difficulty, crate::blockchain::MAX_DIFFICULTY)
+            }
+            ConfigError::BelowRequired { difficulty, required } => {
+                write!(f, "difficulty {} is below the {} retargeting requires", difficulty, required)
+            }
+            ConfigError::ChainStarted { height } => {
+                write!(f, "retargeting can't be changed once blocks are mined (chain is at height {})", height)
+            }
+            ConfigError::NetworkInUse => {
+                write!(f, "network id can't be changed once transactions are confirmed or pending")
+            }
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+// A block or transaction position that isn't in the chain
+#[derive(Debug, Clone, PartialEq)]
+pub enum ChainError {
+    NoSuchBlock { index: u64 },
+    NoSuchTransaction { block: u64, tx: usize },
+}
+
+impl fmt::Display for ChainError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ChainError::NoSuchBlock { index } => write!(f, "there is no block #{}", index),
+            ChainError::NoSuchTransaction { block, tx } => {
+                write!(f, "block #{} has no transaction {}", block, tx)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ChainError {}
+
+// Reasons a string isn't a valid block hash
+#[derive(Debug, Clone, PartialEq)]
+pub enum HashError {
+    WrongLength { len: usize },
+    NotHex,
+    NotBase64,
+}
+
+impl fmt::Display for HashError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            HashError::WrongLength { len } => write!(f, "hash must be 64 hex digits, got {} characters", len),
+            HashError::NotHex => write!(f, "hash contains a non-hex character"),
+            HashError::NotBase64 => write!(f, "hash is not valid base64"),
+        }
+    }
+}
+
+impl std::error::Error for HashError {}
+
+// Reasons a transaction supplied as JSON can't be used
+#[derive(Debug, Clone, PartialEq)]
+pub enum ParseError {
+    Malformed(String),
+    NotAnObject,
+    MissingField { field: &'static str },
+    WrongType { field: &'static str, expected: &'static str },
+    InvalidAmount { amount: f64 },
+    InvalidFee { fee: f64 },
+    SystemSender,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParseError::Malformed(e) => write!(f, "malformed JSON: {}", e),
+            ParseError::NotAnObject => write!(f, "transaction must be a JSON object"),
+            ParseError::MissingField { field } => write!(f, "missing field '{}'", field),
+            ParseError::WrongType { field, expected } => write!(f, "field '{}' must be {}", field, expected),
+            ParseError::InvalidAmount { amount } => write!(f, "invalid amount {}", amount),
+            ParseError::InvalidFee { fee } => write!(f, "invalid fee {}", fee),
+            ParseError::SystemSender => write!(f, "transactions can't be sent from System"),
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
@@ -0,0 +1,180 @@
+use serde::{Serialize, Serializer, Deserialize, Deserializer};
+use std::fmt;
+use std::str::FromStr;
+
+use crate::error::HashError;
+
+const BASE64_ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+// How hashes are written out for people and in saved chains. Hashes are
+// compared as bytes, so the choice never affects validation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HashFormat {
+    // 64 lowercase hex digits
+    #[default]
+    Hex,
+    // 44 characters of standard, padded base64
+    Base64,
+}
+
+// A block hash: the 32 bytes of a SHA-256 digest, shown as 64 lowercase hex
+// digits. Keeping it apart from String means it can't be mixed up with an
+// address or a transaction id.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Hash([u8; 32]);
+
+impl Hash {
+    // Parent hash of the genesis block
+    pub const ZERO: Hash = Hash([0; 32]);
+
+    pub fn from_bytes(bytes: [u8; 32]) -> Self {
+        Self(bytes)
+    }
+
+    pub fn as_bytes(&self) -> &[u8; 32] {
+        &self.0
+    }
+
+    pub fn to_string_in(&self, format: HashFormat) -> String {
+        match format {
+            HashFormat::Hex => self.to_string(),
+            HashFormat::Base64 => {
+                let mut out = String::with_capacity(44);
+                for chunk in self.0.chunks(3) {
+                    let bits = chunk.iter().fold(0u32, |acc, byte| acc << 8 | *byte as u32) << (8 * (3 - chunk.len()));
+                    for i in 0..4 {
+                        if i <= chunk.len() {
+                            out.push(BASE64_ALPHABET[(bits >> (18 - 6 * i) & 0x3f) as usize] as char);
+                        } else {
+                            out.push('=');
+                        }
+                    }
+                }
+                out
+            }
+        }
+    }
+
+    fn from_base64(s: &str) -> Result<Self, HashError> {
+        let digits = s.strip_suffix('=').ok_or(HashError::NotBase64)?;
+        let mut bits: u64 = 0;
+        let mut bytes = Vec::with_capacity(33);
+        for (i, c) in digits.bytes().enumerate() {
+            let value = BASE64_ALPHABET.iter().position(|&d| d == c).ok_or(HashError::NotBase64)?;
+            bits = bits << 6 | value as u64;
+            if i % 4 == 3 {
+                bytes.extend_from_slice(&bits.to_be_bytes()[5..]);
+                bits = 0;
+            }
+        }
+        // 43 digits leave 18 bits, the last 2 of which are padding
+        bytes.extend_from_slice(&(bits >> 2).to_be_bytes()[6..]);
+        Ok(Self(bytes.try_into().map_err(|_| HashError::NotBase64)?))
+    }
+
+    // Number of leading zero hex digits, which is what proof of work counts
+    pub fn leading_zero_digits(&self) -> usize {
+        let mut zeros = 0;
+        for byte in self.0 {
+            if byte == 0 {
+                zeros += 2;
+                continue;
+            }
+            if byte < 0x10 {
+                zeros += 1;
+            }
+            break;
+        }
+        zeros
+    }
+}
+
+impl fmt::Display for Hash {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for byte in self.0 {
+            write!(f, "{:02x}", byte)?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Hash {
+    type Err = HashError;
+
+    // Hex, or the base64 form written by HashFormat::Base64
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.len() == 44 {
+            return Self::from_base64(s);
+        }
+        if s.len() != 64 {
+            return Err(HashError::WrongLength { len: s.len() });
+        }
+        // from_str_radix alone would let a '+' sign through
+        if !s.bytes().all(|c| c.is_ascii_hexdigit()) {
+            return Err(HashError::NotHex);
+        }
+
+        let mut bytes = [0; 32];
+        for (i, byte) in bytes.iter_mut().enumerate() {
+            *byte = u8::from_str_radix(&s[2 * i..2 * i + 2], 16).map_err(|_| HashError::NotHex)?;
+        }
+        Ok(Self(bytes))
+    }
+}
+
+impl Serialize for Hash {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+// Chains saved before hashes were typed wrote the genesis parent as "0"
+impl<'de> Deserialize<'de> for Hash {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        if s == "0" {
+            return Ok(Hash::ZERO);
+        }
+        s.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HEX: &str = "00000a3f1c9e0b4d7a2e6f8190c3b5d4e7f60a1b2c3d4e5f60718293a4b5c6d7";
+
+    #[test]
+    fn hex_round_trips() {
+        let hash: Hash = HEX.parse().unwrap();
+        assert_eq!(hash.to_string(), HEX);
+        assert_eq!(hash.leading_zero_digits(), 5);
+    }
+
+    #[test]
+    fn non_hex_is_rejected() {
+        assert_eq!(HEX.replace('a', "g").parse::<Hash>(), Err(HashError::NotHex));
+        assert_eq!(format!("+{}", &HEX[1..]).parse::<Hash>(), Err(HashError::NotHex));
+        assert_eq!(format!("é{}", &HEX[2..]).parse::<Hash>(), Err(HashError::NotHex));
+    }
+
+    #[test]
+    fn wrong_length_is_rejected() {
+        assert_eq!(HEX[..63].parse::<Hash>(), Err(HashError::WrongLength { len: 63 }));
+        assert_eq!(format!("{}0", HEX).parse::<Hash>(), Err(HashError::WrongLength { len: 65 }));
+        assert_eq!("".parse::<Hash>(), Err(HashError::WrongLength { len: 0 }));
+    }
+
+    #[test]
+    fn base64_round_trips() {
+        let hash: Hash = HEX.parse().unwrap();
+        let base64 = hash.to_string_in(HashFormat::Base64);
+
+        assert_eq!(base64, "AAAKPxyeC016Lm+BkMO11Of2ChssPU5fYHGCk6S1xtc=");
+        assert_eq!(base64.parse::<Hash>(), Ok(hash));
+        assert_eq!(hash.to_string_in(HashFormat::Hex), HEX);
+        assert_eq!(format!("!{}", &base64[1..]).parse::<Hash>(), Err(HashError::NotBase64));
+    }
+}
@@ -0,0 +1,36 @@
+mod analytics;
+mod block;
+mod bloom;
+mod blockchain;
+mod bundle;
+mod error;
+mod hash;
+mod merkle;
+mod persistence;
+mod reward;
+mod rules;
+mod selection;
+mod stake;
+mod storage;
+#[cfg(any(test, feature = "test-util"))]
+mod test_util;
+mod transaction;
+
+pub use block::Block;
+pub use bloom::BloomFilter;
+pub use blockchain::{
+    Blockchain, DifficultyChange, DEFAULT_MAX_DIFFICULTY, DEFAULT_MAX_PENDING, DEFAULT_MIN_DIFFICULTY,
+    MAX_DIFFICULTY, MAX_EXACT_BALANCE, UNITS_PER_COIN, ValidationLevel,
+};
+pub use bundle::{verify_block_bundle, BlockBundle};
+pub use error::{BalanceError, ChainError, ConfigError, HashError, IssuanceError, LoadError, ParseError, RewardSplitError, TxError, ValidationError};
+pub use hash::{Hash, HashFormat};
+pub use merkle::{merkle_proof, merkle_root, transaction_hash, verify_inclusion, Receipt};
+pub use persistence::{Format, FORMAT_VERSION};
+pub use reward::{FixedReward, HalvingReward, RewardPolicy, RewardSetting};
+pub use rules::{DifficultyPolicy, Rules, TransactionValidator};
+pub use selection::{Fifo, HighestFee, SeededRandom, SelectionSetting, SelectionStrategy};
+pub use storage::{FileStorage, Storage};
+#[cfg(any(test, feature = "test-util"))]
+pub use test_util::TestChainBuilder;
+pub use transaction::{parse_transaction, Transaction, SYSTEM_ADDRESS};
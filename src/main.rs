@@ -1,292 +1,196 @@
-use sha2::{Sha256, Digest};
-use serde::{Serialize, Deserialize};
-use chrono::Utc;
 use colored::*;
-use std::fmt;
-
-// Transaction structure
-#[derive(Debug, Clone, Serialize, Deserialize)]
-struct Transaction {
-    sender: String,
-    receiver: String,
-    amount: f64,
-    timestamp: i64,
-}
-
-impl Transaction {
-    fn new(sender: String, receiver: String, amount: f64) -> Self {
-        Self {
-            sender,
-            receiver,
-            amount,
-            timestamp: Utc::now().timestamp(),
-        }
+use rusty_blockchain::{Blockchain, Hash, Transaction, SYSTEM_ADDRESS};
+use std::io::{self, BufRead};
+use std::process;
+
+const DEFAULT_DIFFICULTY: usize = 4;
+const DEFAULT_REWARD: f64 = 100.0;
+
+// Every run starts from the same genesis block. Its hash depends on the
+// difficulty it was mined at, so it is only pinned for the default one.
+const GENESIS_TIMESTAMP: i64 = 1_700_000_000;
+const EXPECTED_GENESIS_HASH: &str = "0000a348c79b628c3a802debaff6775c8d67a9978f654633310e2aede9afef6d";
+
+// Parse `[difficulty] [reward]`, falling back to the defaults for anything
+// not given
+fn parse_args(args: &[String]) -> Result<(usize, f64), String> {
+    if args.len() > 2 {
+        return Err(format!("expected at most 2 arguments, got {}", args.len()));
     }
 
-    fn to_string(&self) -> String {
-        format!("{}{}{}{}", self.sender, self.receiver, self.amount, self.timestamp)
-    }
-}
+    let difficulty = match args.first() {
+        Some(arg) => arg.parse::<usize>().map_err(|_| format!("invalid difficulty '{}'", arg))?,
+        None => DEFAULT_DIFFICULTY,
+    };
+
+    let reward = match args.get(1) {
+        Some(arg) => match arg.parse::<f64>() {
+            Ok(reward) if reward.is_finite() && reward >= 0.0 => reward,
+            _ => return Err(format!("invalid reward '{}'", arg)),
+        },
+        None => DEFAULT_REWARD,
+    };
 
-// Block structure
-#[derive(Debug, Clone, Serialize, Deserialize)]
-struct Block {
-    index: u64,
-    timestamp: i64,
-    transactions: Vec<Transaction>,
-    previous_hash: String,
-    hash: String,
-    nonce: u64,
-    difficulty: usize,
+    Ok((difficulty, reward))
 }
 
-impl Block {
-    fn new(index: u64, transactions: Vec<Transaction>, previous_hash: String, difficulty: usize) -> Self {
-        let timestamp = Utc::now().timestamp();
-        let mut block = Self {
-            index,
-            timestamp,
-            transactions,
-            previous_hash,
-            hash: String::new(),
-            nonce: 0,
-            difficulty,
-        };
-        block.hash = block.calculate_hash();
-        block
+// Parse a `from,to,amount` line read in --stdin mode
+fn parse_line(line: &str) -> Result<(String, String, f64), String> {
+    let fields: Vec<&str> = line.split(',').map(str::trim).collect();
+    let [from, to, amount] = fields[..] else {
+        return Err(format!("expected from,to,amount but got {} field(s)", fields.len()));
+    };
+    if from.is_empty() || to.is_empty() {
+        return Err("sender and receiver can't be empty".to_string());
     }
-
-    fn calculate_hash(&self) -> String {
-        let transactions_str: String = self.transactions
-            .iter()
-            .map(|t| t.to_string())
-            .collect::<Vec<String>>()
-            .join("");
-
-        let block_data = format!(
-            "{}{}{}{}{}",
-            self.index, self.timestamp, transactions_str, self.previous_hash, self.nonce
-        );
-
-        let mut hasher = Sha256::new();
-        hasher.update(block_data.as_bytes());
-        format!("{:x}", hasher.finalize())
+    if from == SYSTEM_ADDRESS {
+        return Err(format!("transactions can't be sent from {}", SYSTEM_ADDRESS));
     }
 
-    fn mine_block(&mut self) {
-        let target = "0".repeat(self.difficulty);
-        
-        println!("\n{}", "⛏️  Mining block...".bright_yellow().bold());
-        print!("Nonce: ");
-
-        while !self.hash.starts_with(&target) {
-            self.nonce += 1;
-            self.hash = self.calculate_hash();
-            
-            // Show progress every 10000 attempts
-            if self.nonce % 10000 == 0 {
-                print!("{} ", self.nonce.to_string().bright_cyan());
-                std::io::Write::flush(&mut std::io::stdout()).unwrap();
-            }
-        }
-
-        println!("\n{} Block mined! Hash: {}", 
-            "✓".bright_green().bold(), 
-            self.hash.bright_green()
-        );
-        println!("Nonce found: {}", self.nonce.to_string().bright_cyan().bold());
+    match amount.parse::<f64>() {
+        Ok(amount) if amount.is_finite() && amount > 0.0 => Ok((from.to_string(), to.to_string(), amount)),
+        _ => Err(format!("invalid amount '{}'", amount)),
     }
 }
 
-impl fmt::Display for Block {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "\n{}\n", "═".repeat(80).bright_blue())?;
-        write!(f, "{} {}\n", "Block #".bright_white().bold(), self.index.to_string().bright_cyan().bold())?;
-        write!(f, "{}\n", "─".repeat(80).bright_blue())?;
-        write!(f, "{}: {}\n", "Timestamp".bright_white(), self.timestamp)?;
-        write!(f, "{}: {}\n", "Previous Hash".bright_white(), self.previous_hash.bright_yellow())?;
-        write!(f, "{}: {}\n", "Hash".bright_white(), self.hash.bright_green())?;
-        write!(f, "{}: {}\n", "Nonce".bright_white(), self.nonce.to_string().bright_cyan())?;
-        write!(f, "{}: {}\n", "Difficulty".bright_white(), self.difficulty)?;
-        write!(f, "\n{}\n", "Transactions:".bright_white().bold())?;
-        
-        for (i, tx) in self.transactions.iter().enumerate() {
-            write!(f, "  {}. {} {} → {} {} coins\n",
-                i + 1,
-                tx.sender.bright_magenta(),
-                "→".bright_white(),
-                tx.receiver.bright_magenta(),
-                tx.amount.to_string().bright_yellow()
-            )?;
-        }
-        write!(f, "{}\n", "═".repeat(80).bright_blue())
-    }
+// Copy of `blockchain` with the amount of transaction `tx` in block `block`
+// rewritten, the way someone editing a saved chain file would, without
+// re-mining anything
+fn tampered(blockchain: &Blockchain, block: usize, tx: usize, amount: f64) -> Option<Blockchain> {
+    let mut json = Vec::new();
+    blockchain.write_json(&mut json).ok()?;
+    let mut value: serde_json::Value = serde_json::from_slice(&json).ok()?;
+    let transaction = value["chain"].get_mut(block)?["transactions"].get_mut(tx)?;
+    transaction["amount"] = amount.into();
+    Blockchain::from_json(&value.to_string()).ok()
 }
 
-// Blockchain structure
-struct Blockchain {
-    chain: Vec<Block>,
-    difficulty: usize,
-    pending_transactions: Vec<Transaction>,
-    mining_reward: f64,
-}
-
-impl Blockchain {
-    fn new(difficulty: usize, mining_reward: f64) -> Self {
-        let mut blockchain = Self {
-            chain: Vec::new(),
-            difficulty,
-            pending_transactions: Vec::new(),
-            mining_reward,
-        };
-        blockchain.create_genesis_block();
-        blockchain
-    }
-
-    fn create_genesis_block(&mut self) {
-        let genesis_tx = Transaction::new(
-            "System".to_string(),
-            "Genesis".to_string(),
-            0.0,
-        );
-        let mut genesis_block = Block::new(0, vec![genesis_tx], "0".to_string(), self.difficulty);
-        genesis_block.mine_block();
-        self.chain.push(genesis_block);
-    }
-
-    fn get_latest_block(&self) -> &Block {
-        self.chain.last().unwrap()
-    }
-
-    fn add_transaction(&mut self, transaction: Transaction) {
-        self.pending_transactions.push(transaction);
-        println!("{} Transaction added to pending pool", "✓".bright_green().bold());
-    }
-
-    fn mine_pending_transactions(&mut self, miner_address: String) {
-        // Add mining reward transaction
-        let reward_tx = Transaction::new(
-            "System".to_string(),
-            miner_address.clone(),
-            self.mining_reward,
-        );
-        self.pending_transactions.push(reward_tx);
-
-        let previous_hash = self.get_latest_block().hash.clone();
-        let index = self.chain.len() as u64;
-        
-        let mut new_block = Block::new(
-            index,
-            self.pending_transactions.clone(),
-            previous_hash,
-            self.difficulty,
-        );
-
-        new_block.mine_block();
-        self.chain.push(new_block);
-
-        self.pending_transactions = Vec::new();
-    }
-
-    fn is_chain_valid(&self) -> bool {
-        for i in 1..self.chain.len() {
-            let current_block = &self.chain[i];
-            let previous_block = &self.chain[i - 1];
-
-            // Verify hash is correct
-            if current_block.hash != current_block.calculate_hash() {
-                println!("{} Block #{} has invalid hash!", "✗".bright_red().bold(), i);
-                return false;
-            }
-
-            // Verify chain linkage
-            if current_block.previous_hash != previous_block.hash {
-                println!("{} Block #{} has invalid previous hash!", "✗".bright_red().bold(), i);
-                return false;
-            }
-
-            // Verify proof of work
-            let target = "0".repeat(current_block.difficulty);
-            if !current_block.hash.starts_with(&target) {
-                println!("{} Block #{} has invalid proof of work!", "✗".bright_red().bold(), i);
-                return false;
+// Queue a transaction for every `from,to,amount` line on stdin, then mine
+// them. Blank lines are skipped and bad lines reported and skipped.
+fn submit_from_stdin(blockchain: &mut Blockchain) {
+    println!("\n{}", "📝 Reading transactions from stdin...".bright_yellow().bold());
+    for (number, line) in io::stdin().lock().lines().enumerate() {
+        let line = match line {
+            Ok(line) => line,
+            Err(e) => {
+                eprintln!("{} could not read stdin: {}", "✗".bright_red().bold(), e);
+                break;
             }
+        };
+        if line.trim().is_empty() {
+            continue;
         }
 
-        true
-    }
-
-    fn get_balance(&self, address: &str) -> f64 {
-        let mut balance = 0.0;
-
-        for block in &self.chain {
-            for tx in &block.transactions {
-                if tx.sender == address {
-                    balance -= tx.amount;
-                }
-                if tx.receiver == address {
-                    balance += tx.amount;
-                }
+        match parse_line(&line) {
+            Ok((from, to, amount)) => {
+                let nonce = blockchain.next_nonce(&from);
+                submit(blockchain, Transaction::new(from, to, amount).with_nonce(nonce));
             }
+            Err(e) => println!("{} Line {} skipped: {}", "✗".bright_red().bold(), number + 1, e),
         }
-
-        balance
     }
 
-    fn display(&self) {
-        println!("\n{}", "╔═══════════════════════════════════════════════════════════════════════════════╗".bright_blue().bold());
-        println!("{}", "║                           🔗 RUSTY BLOCKCHAIN 🔗                             ║".bright_blue().bold());
-        println!("{}", "╚═══════════════════════════════════════════════════════════════════════════════╝".bright_blue().bold());
-        
-        for block in &self.chain {
-            print!("{}", block);
-        }
-    }
+    println!("\n{}", "⛏️  Mining Block #2...".bright_yellow().bold());
+    blockchain.mine_pending_transactions("Miner1".to_string());
 }
 
-fn main() {
-    println!("\n{}", "🚀 Welcome to Rusty Blockchain! 🚀".bright_cyan().bold());
-    println!("{}\n", "Building a blockchain from scratch...".bright_white());
-
-    // Create blockchain with difficulty 4 and mining reward of 100 coins
-    let mut blockchain = Blockchain::new(4, 100.0);
+fn submit(blockchain: &mut Blockchain, transaction: Transaction) {
+    if let Err(e) = blockchain.add_transaction(transaction) {
+        println!("{} Transaction rejected: {}", "✗".bright_red().bold(), e);
+    }
+}
 
+// The fixed demo transactions, mined into blocks 2 and 3
+fn run_script(blockchain: &mut Blockchain) {
     println!("\n{}", "📝 Adding transactions...".bright_yellow().bold());
-    
+
     // Add some transactions
-    blockchain.add_transaction(Transaction::new(
+    submit(blockchain, Transaction::new(
         "Alice".to_string(),
         "Bob".to_string(),
         50.0,
     ));
 
-    blockchain.add_transaction(Transaction::new(
-        "Bob".to_string(),
+    submit(blockchain, Transaction::new(
+        "Alice".to_string(),
         "Charlie".to_string(),
-        25.0,
-    ));
+        20.0,
+    ).with_nonce(1)); // Alice's second transaction
 
-    // Mine block 1
-    println!("\n{}", "⛏️  Mining Block #1...".bright_yellow().bold());
+    // Mine block 2
+    println!("\n{}", "⛏️  Mining Block #2...".bright_yellow().bold());
     blockchain.mine_pending_transactions("Miner1".to_string());
 
     // Add more transactions
     println!("\n{}", "📝 Adding more transactions...".bright_yellow().bold());
-    blockchain.add_transaction(Transaction::new(
+    submit(blockchain, Transaction::new(
+        "Bob".to_string(),
+        "Charlie".to_string(),
+        25.0,
+    ));
+
+    submit(blockchain, Transaction::new(
         "Charlie".to_string(),
         "Alice".to_string(),
         10.0,
     ));
 
-    blockchain.add_transaction(Transaction::new(
+    submit(blockchain, Transaction::new(
         "Alice".to_string(),
         "Miner1".to_string(),
         5.0,
-    ));
+    ).with_nonce(2));
 
-    // Mine block 2
-    println!("\n{}", "⛏️  Mining Block #2...".bright_yellow().bold());
+    // Charlie can't spend more than he has
+    submit(blockchain, Transaction::new(
+        "Charlie".to_string(),
+        "Bob".to_string(),
+        1000.0,
+    ).with_nonce(1));
+
+    // Mine block 3
+    println!("\n{}", "⛏️  Mining Block #3...".bright_yellow().bold());
     blockchain.mine_pending_transactions("Miner1".to_string());
+}
+
+fn main() {
+    let mut args: Vec<String> = std::env::args().skip(1).collect();
+    let read_stdin = args.iter().any(|arg| arg == "--stdin");
+    args.retain(|arg| arg != "--stdin");
+    let (difficulty, reward) = match parse_args(&args) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("{} {}", "✗".bright_red().bold(), e);
+            eprintln!("usage: rusty_blockchain [--stdin] [difficulty] [reward]");
+            process::exit(1);
+        }
+    };
+
+    println!("\n{}", "🚀 Welcome to Rusty Blockchain! 🚀".bright_cyan().bold());
+    println!("{}\n", "Building a blockchain from scratch...".bright_white());
+
+    // Create blockchain, by default with difficulty 4 and a mining reward of 100 coins
+    let mut blockchain = match Blockchain::with_genesis_timestamp(difficulty, reward, GENESIS_TIMESTAMP) {
+        Ok(blockchain) => blockchain,
+        Err(e) => {
+            eprintln!("{} {}", "✗".bright_red().bold(), e);
+            process::exit(1);
+        }
+    };
+    if difficulty == DEFAULT_DIFFICULTY {
+        let expected: Hash = EXPECTED_GENESIS_HASH.parse().expect("pinned genesis hash is valid hex");
+        assert!(blockchain.verify_genesis(&expected), "genesis block doesn't match the pinned hash");
+    }
+
+    // Mine block 1 so Alice has coins to spend
+    println!("\n{}", "⛏️  Mining Block #1 (Alice earns the reward)...".bright_yellow().bold());
+    blockchain.mine_pending_transactions("Alice".to_string());
+
+    if read_stdin {
+        submit_from_stdin(&mut blockchain);
+    } else {
+        run_script(&mut blockchain);
+    }
 
     // Display the entire blockchain
     blockchain.display();
@@ -295,13 +199,19 @@ fn main() {
     println!("\n{}", "💰 Account Balances:".bright_yellow().bold());
     println!("{}\n", "─".repeat(50).bright_blue());
     
-    let addresses = vec!["Alice", "Bob", "Charlie", "Miner1"];
-    for address in addresses {
-        let balance = blockchain.get_balance(address);
-        println!("{}: {} coins", 
-            address.bright_magenta().bold(), 
-            balance.to_string().bright_green()
-        );
+    let addresses: Vec<String> = if read_stdin {
+        blockchain.top_balances(usize::MAX).into_iter().map(|(address, _)| address).collect()
+    } else {
+        ["Alice", "Bob", "Charlie", "Miner1"].map(String::from).to_vec()
+    };
+    for address in &addresses {
+        match blockchain.get_balance(address) {
+            Ok(balance) => println!("{}: {} coins", 
+                address.bright_magenta().bold(), 
+                balance.to_string().bright_green()
+            ),
+            Err(e) => println!("{} {}", "✗".bright_red().bold(), e),
+        }
     }
 
     // Validate blockchain
@@ -314,10 +224,9 @@ fn main() {
 
     // Demonstrate tampering detection
     println!("\n{}", "🔓 Attempting to tamper with blockchain...".bright_red().bold());
-    if blockchain.chain.len() > 1 {
-        blockchain.chain[1].transactions[0].amount = 1000.0;
+    if let Some(blockchain) = tampered(&blockchain, 1, 0, 1000.0) {
         println!("Changed transaction amount in Block #1");
-        
+
         println!("\n{}", "🔍 Re-validating blockchain...".bright_yellow().bold());
         if blockchain.is_chain_valid() {
             println!("{} Blockchain is still valid!", "✓".bright_green().bold());
@@ -327,4 +236,65 @@ fn main() {
     }
 
     println!("\n{}", "🎉 Demo complete!".bright_cyan().bold());
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(values: &[&str]) -> Vec<String> {
+        values.iter().map(|value| value.to_string()).collect()
+    }
+
+    #[test]
+    fn defaults_when_nothing_is_given() {
+        assert_eq!(parse_args(&[]), Ok((DEFAULT_DIFFICULTY, DEFAULT_REWARD)));
+        assert_eq!(parse_args(&args(&["2"])), Ok((2, DEFAULT_REWARD)));
+        assert_eq!(parse_args(&args(&["1", "12.5"])), Ok((1, 12.5)));
+    }
+
+    #[test]
+    fn invalid_arguments_are_refused() {
+        assert_eq!(parse_args(&args(&["hard"])), Err("invalid difficulty 'hard'".to_string()));
+        assert_eq!(parse_args(&args(&["-1"])), Err("invalid difficulty '-1'".to_string()));
+        assert_eq!(parse_args(&args(&["2", "-5"])), Err("invalid reward '-5'".to_string()));
+        assert_eq!(parse_args(&args(&["2", "inf"])), Err("invalid reward 'inf'".to_string()));
+        assert_eq!(parse_args(&args(&["2", "5", "x"])), Err("expected at most 2 arguments, got 3".to_string()));
+    }
+
+    #[test]
+    fn stdin_lines_are_parsed() {
+        assert_eq!(parse_line("Alice,Bob,50"), Ok(("Alice".to_string(), "Bob".to_string(), 50.0)));
+        assert_eq!(parse_line(" Alice , Bob , 2.5 "), Ok(("Alice".to_string(), "Bob".to_string(), 2.5)));
+    }
+
+    #[test]
+    fn malformed_stdin_lines_are_refused() {
+        assert_eq!(parse_line("Alice,Bob"), Err("expected from,to,amount but got 2 field(s)".to_string()));
+        assert_eq!(parse_line("Alice,Bob,1,2"), Err("expected from,to,amount but got 4 field(s)".to_string()));
+        assert_eq!(parse_line(",Bob,1"), Err("sender and receiver can't be empty".to_string()));
+        assert_eq!(parse_line("System,Bob,1000"), Err("transactions can't be sent from System".to_string()));
+        assert_eq!(parse_line("Alice,Bob,lots"), Err("invalid amount 'lots'".to_string()));
+        assert_eq!(parse_line("Alice,Bob,-3"), Err("invalid amount '-3'".to_string()));
+        assert_eq!(parse_line("Alice,Bob,NaN"), Err("invalid amount 'NaN'".to_string()));
+    }
+
+    #[test]
+    fn tampered_copy_fails_validation() {
+        let mut chain = Blockchain::with_genesis_timestamp(0, DEFAULT_REWARD, GENESIS_TIMESTAMP).unwrap();
+        chain.mine_pending_transactions("Alice".to_string());
+
+        let copy = tampered(&chain, 1, 0, 1000.0).unwrap();
+        assert_eq!(copy.get_balance("Alice"), Ok(1000.0));
+        assert!(!copy.is_chain_valid());
+        assert!(chain.is_chain_valid());
+        assert!(tampered(&chain, 5, 0, 1000.0).is_none());
+    }
+
+    #[test]
+    fn demo_genesis_matches_the_pinned_hash() {
+        let chain = Blockchain::with_genesis_timestamp(DEFAULT_DIFFICULTY, DEFAULT_REWARD, GENESIS_TIMESTAMP).unwrap();
+        let expected: Hash = EXPECTED_GENESIS_HASH.parse().unwrap();
+        assert!(chain.verify_genesis(&expected));
+    }
+}
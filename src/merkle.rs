@@ -0,0 +1,214 @@
+use sha2::{Sha256, Digest};
+
+use serde::{Serialize, Deserialize};
+
+use crate::block::Block;
+use crate::blockchain::Blockchain;
+use crate::transaction::Transaction;
+
+// Merkle tree over transaction hashes. Pairs are hashed in sorted order, so a
+// proof only needs the sibling hashes and not whether each sibling sits on
+// the left or the right. An odd node at the end of a level is paired with itself.
+
+fn hash_pair(a: &str, b: &str) -> String {
+    let (first, second) = if a <= b { (a, b) } else { (b, a) };
+    let mut hasher = Sha256::new();
+    hasher.update(first.as_bytes());
+    hasher.update(second.as_bytes());
+    format!("{:x}", hasher.finalize())
+}
+
+pub fn transaction_hash(tx: &Transaction) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(tx.hash_preimage());
+    format!("{:x}", hasher.finalize())
+}
+
+fn next_level(level: &[String]) -> Vec<String> {
+    level.chunks(2)
+        .map(|pair| hash_pair(&pair[0], pair.get(1).unwrap_or(&pair[0])))
+        .collect()
+}
+
+// Root over already hashed leaves, all zeros when there are none
+fn root_of(mut level: Vec<String>) -> String {
+    if level.is_empty() {
+        return "0".repeat(64);
+    }
+
+    while level.len() > 1 {
+        level = next_level(&level);
+    }
+    level.remove(0)
+}
+
+pub fn merkle_root(transactions: &[Transaction]) -> String {
+    root_of(transactions.iter().map(transaction_hash).collect())
+}
+
+// Sibling hashes from the transaction at `index` up to the root
+pub fn merkle_proof(transactions: &[Transaction], index: usize) -> Option<Vec<String>> {
+    if index >= transactions.len() {
+        return None;
+    }
+
+    let mut level: Vec<String> = transactions.iter().map(transaction_hash).collect();
+    let mut position = index;
+    let mut proof = Vec::new();
+
+    while level.len() > 1 {
+        let sibling = level.get(position ^ 1).unwrap_or(&level[position]);
+        proof.push(sibling.clone());
+        level = next_level(&level);
+        position /= 2;
+    }
+
+    Some(proof)
+}
+
+pub fn verify_inclusion(tx: &Transaction, proof: &[String], merkle_root: &str) -> bool {
+    let computed = proof.iter()
+        .fold(transaction_hash(tx), |hash, sibling| hash_pair(&hash, sibling));
+    computed == merkle_root
+}
+
+// Proof that a transaction was mined, checkable against the Merkle root of
+// block `block_index` without the block's other transactions
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Receipt {
+    pub tx_id: String,
+    pub block_index: u64,
+    pub proof: Vec<String>,
+}
+
+impl Receipt {
+    pub(crate) fn for_transaction(block: &Block, index: usize) -> Option<Receipt> {
+        Some(Receipt {
+            tx_id: transaction_hash(block.transactions.get(index)?),
+            block_index: block.index,
+            proof: merkle_proof(&block.transactions, index)?,
+        })
+    }
+}
+
+impl Blockchain {
+    // Merkle root over the (address, balance) pairs of account_balances, in
+    // address order, so it covers the same accounts as top_balances. Nodes
+    // holding the same balances get the same root however their chains got
+    // there; a leaf hashes the address, a zero byte and the balance's bits.
+    pub fn state_root(&self) -> String {
+        let mut balances: Vec<(&str, f64)> = self.account_balances().collect();
+        balances.sort_by(|a, b| a.0.cmp(b.0));
+
+        root_of(balances.iter()
+            .map(|(address, balance)| {
+                let mut hasher = Sha256::new();
+                hasher.update(address.as_bytes());
+                hasher.update([0]);
+                hasher.update(balance.to_bits().to_be_bytes());
+                format!("{:x}", hasher.finalize())
+            })
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blockchain::Blockchain;
+    use crate::test_util::TestChainBuilder;
+    use crate::transaction::Transaction;
+
+    fn transactions() -> Vec<Transaction> {
+        ["Bob", "Carol", "Dave", "Erin", "Frank"].iter()
+            .map(|receiver| Transaction::new("Alice".to_string(), receiver.to_string(), 1.0))
+            .collect()
+    }
+
+    #[test]
+    fn proof_verifies_against_the_root() {
+        let transactions = transactions();
+        let root = merkle_root(&transactions);
+
+        for (i, tx) in transactions.iter().enumerate() {
+            let proof = merkle_proof(&transactions, i).unwrap();
+            assert!(verify_inclusion(tx, &proof, &root));
+        }
+    }
+
+    #[test]
+    fn proof_with_a_wrong_sibling_fails() {
+        let transactions = transactions();
+        let root = merkle_root(&transactions);
+        let mut proof = merkle_proof(&transactions, 1).unwrap();
+        proof[0] = transaction_hash(&transactions[2]);
+
+        assert!(!verify_inclusion(&transactions[1], &proof, &root));
+    }
+
+    #[test]
+    fn proof_of_the_wrong_length_fails() {
+        let transactions = transactions();
+        let root = merkle_root(&transactions);
+        let proof = merkle_proof(&transactions, 3).unwrap();
+
+        assert!(!verify_inclusion(&transactions[3], &proof[..proof.len() - 1], &root));
+        let mut longer = proof.clone();
+        longer.push(root.clone());
+        assert!(!verify_inclusion(&transactions[3], &longer, &root));
+    }
+
+    #[test]
+    fn every_mined_transaction_gets_a_verifying_receipt() {
+        let mut chain = Blockchain::new_for_test();
+        chain.mine_pending_transactions("Alice".to_string());
+        chain.add_transaction(Transaction::new("Alice".to_string(), "Bob".to_string(), 1.0)).unwrap();
+        chain.add_transaction(Transaction::new("Alice".to_string(), "Carol".to_string(), 2.0).with_nonce(1)).unwrap();
+
+        let receipts = chain.mine_pending_transactions("Miner".to_string());
+        let block = chain.get_latest_block();
+        assert_eq!(receipts.len(), block.transactions().len());
+
+        for (receipt, tx) in receipts.iter().zip(block.transactions()) {
+            assert_eq!(receipt.block_index, block.index());
+            assert_eq!(receipt.tx_id, tx.id());
+            assert!(verify_inclusion(tx, &receipt.proof, &block.merkle_root()));
+        }
+    }
+
+    #[test]
+    fn state_root_follows_the_balances() {
+        let build = |amounts: &[f64]| {
+            let mut builder = TestChainBuilder::new().mine("Alice");
+            for amount in amounts {
+                builder = builder.tx("Alice", "Bob", *amount);
+            }
+            builder.mine("Miner").build()
+        };
+
+        assert_eq!(build(&[30.0]).state_root(), build(&[30.0]).state_root());
+        // Same balances reached through different transactions
+        assert_eq!(build(&[30.0]).state_root(), build(&[10.0, 20.0]).state_root());
+        assert_ne!(build(&[30.0]).state_root(), build(&[31.0]).state_root());
+    }
+
+    #[test]
+    fn state_root_leaves_out_empty_and_pseudo_addresses() {
+        let chain = TestChainBuilder::new().mine("Alice").tx("Alice", "Bob", 100.0).mine("Miner").build();
+        assert_eq!(chain.balances()["Alice"], 0.0);
+
+        let accounts = |chain: &Blockchain| {
+            let mut addresses: Vec<String> = chain.account_balances().map(|(address, _)| address.to_string()).collect();
+            addresses.sort();
+            addresses
+        };
+        assert_eq!(accounts(&chain), vec!["Bob", "Miner"]);
+        let mut top: Vec<String> = chain.top_balances(usize::MAX).into_iter().map(|(address, _)| address).collect();
+        top.sort();
+        assert_eq!(top, accounts(&chain));
+
+        // Alice emptying her balance gives the same root as never having had one
+        let direct = TestChainBuilder::new().mine("Bob").mine("Miner").build();
+        assert_eq!(chain.state_root(), direct.state_root());
+    }
+}
@@ -0,0 +1,548 @@
+use serde::{Serialize, Deserialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+
+use crate::block::Block;
+use crate::blockchain::{
+    check_difficulty, Blockchain, DifficultyChange, DEFAULT_MAX_DIFFICULTY, DEFAULT_MAX_PENDING,
+    DEFAULT_MIN_DIFFICULTY,
+};
+use crate::error::LoadError;
+use crate::hash::HashFormat;
+use crate::reward::RewardSetting;
+use crate::selection::SelectionSetting;
+use crate::transaction::Transaction;
+
+// Version written into every saved chain. Files from before the version tag
+// existed are treated as version 0.
+pub const FORMAT_VERSION: u32 = 2;
+
+// Block header in a packed binary layout: index, timestamp, nonce and
+// difficulty as 8 bytes each, plus both hashes as 32 raw bytes
+const BINARY_HEADER_BYTES: usize = 4 * 8 + 2 * 32;
+
+// Encoding to estimate the on-disk size for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    // Blocks as written by write_json
+    Json,
+    // Packed headers followed by the canonical transaction encoding
+    Binary,
+}
+
+// On-disk layout of a saved chain
+#[derive(Serialize, Deserialize)]
+struct ChainFile {
+    version: u32,
+    difficulty: usize,
+    mining_reward: f64,
+    chain: Vec<Block>,
+    pending_transactions: Vec<Transaction>,
+    #[serde(default)]
+    target_block_time: Option<i64>,
+    #[serde(default)]
+    difficulty_history: Vec<DifficultyChange>,
+    #[serde(default = "default_max_pending")]
+    max_pending: usize,
+    #[serde(default)]
+    dust_threshold: f64,
+    #[serde(default)]
+    min_block_fees: f64,
+    #[serde(default = "default_epoch_length")]
+    epoch_length: u64,
+    #[serde(default = "default_min_difficulty")]
+    min_difficulty: usize,
+    #[serde(default = "default_max_difficulty")]
+    max_difficulty: usize,
+    #[serde(default)]
+    ignore_empty_blocks: bool,
+    #[serde(default)]
+    reward_confirmations: u64,
+    #[serde(default)]
+    metadata: HashMap<String, String>,
+    #[serde(default)]
+    network_id: Option<String>,
+    #[serde(default)]
+    reward_policy: Option<RewardSetting>,
+    #[serde(default)]
+    selection: Option<SelectionSetting>,
+    #[serde(default = "default_max_block_transactions")]
+    max_block_transactions: usize,
+    #[serde(default = "default_mining_threads")]
+    mining_threads: usize,
+    #[serde(default)]
+    hash_format: HashFormat,
+}
+
+fn default_max_pending() -> usize {
+    DEFAULT_MAX_PENDING
+}
+
+fn default_epoch_length() -> u64 {
+    1
+}
+
+fn default_min_difficulty() -> usize {
+    DEFAULT_MIN_DIFFICULTY
+}
+
+fn default_max_difficulty() -> usize {
+    DEFAULT_MAX_DIFFICULTY
+}
+
+fn default_max_block_transactions() -> usize {
+    usize::MAX
+}
+
+fn default_mining_threads() -> usize {
+    1
+}
+
+// Write `,"name":value` into a JSON object being streamed out
+fn write_field<W: Write, T: Serialize>(writer: &mut W, name: &str, value: &T) -> io::Result<()> {
+    write!(writer, ",\"{}\":", name)?;
+    serde_json::to_writer(&mut *writer, value).map_err(io::Error::other)
+}
+
+// `block` as JSON, with its hashes rewritten when `format` isn't the hex
+// that Block serializes to
+fn block_json(block: &Block, format: HashFormat) -> serde_json::Result<Value> {
+    let mut value = serde_json::to_value(block)?;
+    if format != HashFormat::Hex {
+        value["hash"] = Value::from(block.hash.to_string_in(format));
+        value["previous_hash"] = Value::from(block.previous_hash.to_string_in(format));
+    }
+    Ok(value)
+}
+
+// Bring an older file up to FORMAT_VERSION, one version at a time
+fn migrate(mut value: Value, from: u32) -> Value {
+    let mut version = from;
+    while version < FORMAT_VERSION {
+        match version {
+            // v0 -> v1: only the version tag was added, the newer settings
+            // fall back to their defaults when deserializing
+            0 => value["version"] = Value::from(1),
+            // v1 -> v2: the mining settings were added. With no policy saved
+            // the chain loads with FixedReward(mining_reward), as before.
+            _ => value["version"] = Value::from(2),
+        }
+        version += 1;
+    }
+    value
+}
+
+impl Blockchain {
+    pub fn save_to_file<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let file = ChainFile {
+            version: FORMAT_VERSION,
+            difficulty: self.difficulty,
+            mining_reward: self.mining_reward,
+            chain: self.chain.clone(),
+            pending_transactions: self.pending_transactions.clone(),
+            target_block_time: self.target_block_time,
+            difficulty_history: self.difficulty_history.clone(),
+            max_pending: self.max_pending,
+            dust_threshold: self.dust_threshold,
+            min_block_fees: self.min_block_fees,
+            epoch_length: self.epoch_length,
+            min_difficulty: self.min_difficulty,
+            max_difficulty: self.max_difficulty,
+            ignore_empty_blocks: self.ignore_empty_blocks,
+            reward_confirmations: self.reward_confirmations,
+            metadata: self.metadata.clone(),
+            network_id: self.network_id.clone(),
+            reward_policy: self.reward_policy.setting(),
+            selection: self.selection.setting(),
+            max_block_transactions: self.max_block_transactions,
+            mining_threads: self.mining_threads,
+            hash_format: self.hash_format,
+        };
+        let json = match self.hash_format {
+            HashFormat::Hex => serde_json::to_string_pretty(&file),
+            format => {
+                let mut value = serde_json::to_value(&file).map_err(io::Error::other)?;
+                let chain = self.chain.iter()
+                    .map(|block| block_json(block, format))
+                    .collect::<serde_json::Result<Vec<Value>>>()
+                    .map_err(io::Error::other)?;
+                value["chain"] = Value::from(chain);
+                serde_json::to_string_pretty(&value)
+            }
+        };
+        fs::write(path, json.map_err(io::Error::other)?)
+    }
+
+    // Same document as save_to_file (minus the pretty printing), but written
+    // one block at a time so the whole chain never sits in memory as a string
+    pub fn write_json<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        write!(writer, "{{\"version\":{}", FORMAT_VERSION)?;
+        write_field(&mut writer, "difficulty", &self.difficulty)?;
+        write_field(&mut writer, "mining_reward", &self.mining_reward)?;
+
+        write!(writer, ",\"chain\":[")?;
+        for (i, block) in self.chain.iter().enumerate() {
+            if i > 0 {
+                write!(writer, ",")?;
+            }
+            let written = match self.hash_format {
+                HashFormat::Hex => serde_json::to_writer(&mut writer, block),
+                format => serde_json::to_writer(&mut writer, &block_json(block, format).map_err(io::Error::other)?),
+            };
+            written.map_err(io::Error::other)?;
+        }
+        write!(writer, "]")?;
+
+        write_field(&mut writer, "pending_transactions", &self.pending_transactions)?;
+        write_field(&mut writer, "target_block_time", &self.target_block_time)?;
+        write_field(&mut writer, "difficulty_history", &self.difficulty_history)?;
+        write_field(&mut writer, "max_pending", &self.max_pending)?;
+        write_field(&mut writer, "dust_threshold", &self.dust_threshold)?;
+        write_field(&mut writer, "min_block_fees", &self.min_block_fees)?;
+        write_field(&mut writer, "epoch_length", &self.epoch_length)?;
+        write_field(&mut writer, "min_difficulty", &self.min_difficulty)?;
+        write_field(&mut writer, "max_difficulty", &self.max_difficulty)?;
+        write_field(&mut writer, "ignore_empty_blocks", &self.ignore_empty_blocks)?;
+        write_field(&mut writer, "reward_confirmations", &self.reward_confirmations)?;
+        write_field(&mut writer, "metadata", &self.metadata)?;
+        write_field(&mut writer, "network_id", &self.network_id)?;
+        write_field(&mut writer, "reward_policy", &self.reward_policy.setting())?;
+        write_field(&mut writer, "selection", &self.selection.setting())?;
+        write_field(&mut writer, "max_block_transactions", &self.max_block_transactions)?;
+        write_field(&mut writer, "mining_threads", &self.mining_threads)?;
+        write_field(&mut writer, "hash_format", &self.hash_format)?;
+        write!(writer, "}}")?;
+        writer.flush()
+    }
+
+    // Every transaction on the chain, rewards included, as one JSON object
+    // per line in chain order. The pending pool is left out.
+    pub fn transaction_feed<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        for tx in self.chain.iter().flat_map(|block| &block.transactions) {
+            serde_json::to_writer(&mut writer, tx).map_err(io::Error::other)?;
+            writeln!(writer)?;
+        }
+        writer.flush()
+    }
+
+    // Bytes the chain's blocks would take in `format`; settings and the
+    // pending pool aren't counted
+    pub fn estimated_size_on_disk(&self, format: Format) -> usize {
+        match format {
+            Format::Json => self.chain.iter()
+                .map(|block| serde_json::to_vec(block).map_or(0, |json| json.len()))
+                .sum(),
+            Format::Binary => self.estimated_size_bytes() + self.chain.len() * BINARY_HEADER_BYTES,
+        }
+    }
+
+    pub fn load_from_file<P: AsRef<Path>>(path: P) -> Result<Self, LoadError> {
+        Self::from_json(&fs::read_to_string(path)?)
+    }
+
+    // The write_json document as lowercase hex, for pasting a small chain
+    // into source code or a QR code
+    pub fn to_hex(&self) -> String {
+        let mut json = Vec::new();
+        // Writing into a Vec can't fail
+        self.write_json(&mut json).unwrap();
+        json.iter().map(|byte| format!("{:02x}", byte)).collect()
+    }
+
+    pub fn from_hex(s: &str) -> Result<Self, LoadError> {
+        let invalid = || LoadError::Parse(serde::de::Error::custom("invalid hex dump"));
+        if !s.len().is_multiple_of(2) {
+            return Err(invalid());
+        }
+        let bytes = (0..s.len())
+            .step_by(2)
+            .map(|i| s.get(i..i + 2).and_then(|digits| u8::from_str_radix(digits, 16).ok()))
+            .collect::<Option<Vec<u8>>>()
+            .ok_or_else(invalid)?;
+        let json = String::from_utf8(bytes).map_err(|_| invalid())?;
+        Self::from_json(&json)
+    }
+
+    // A chain from the document save_to_file and write_json produce. The
+    // blocks aren't validated, so check is_chain_valid on anything that
+    // didn't come from a trusted source.
+    pub fn from_json(json: &str) -> Result<Self, LoadError> {
+        let value: Value = serde_json::from_str(json)?;
+
+        let version = match value.get("version") {
+            Some(v) => v.as_u64().map(|v| v as u32).unwrap_or(u32::MAX),
+            None => 0,
+        };
+        if version > FORMAT_VERSION {
+            return Err(LoadError::UnsupportedVersion(version));
+        }
+
+        let file: ChainFile = serde_json::from_value(migrate(value, version))?;
+        if file.chain.is_empty() {
+            return Err(LoadError::Parse(serde::de::Error::custom("chain has no genesis block")));
+        }
+
+        // Mining at a difficulty no hash can meet would never finish
+        check_difficulty(file.difficulty).map_err(|e| LoadError::Parse(serde::de::Error::custom(e)))?;
+
+        let mut blockchain = Blockchain::empty(file.difficulty, file.mining_reward);
+        blockchain.chain = file.chain;
+        blockchain.reindex();
+        blockchain.pending_transactions = file.pending_transactions;
+        blockchain.target_block_time = file.target_block_time;
+        blockchain.difficulty_history = file.difficulty_history;
+        blockchain.max_pending = file.max_pending;
+        blockchain.dust_threshold = file.dust_threshold;
+        blockchain.min_block_fees = file.min_block_fees;
+        blockchain.epoch_length = file.epoch_length;
+        // A hand-edited file may have the bounds crossed or out of range
+        blockchain.apply_difficulty_bounds(file.min_difficulty, file.max_difficulty);
+        blockchain.ignore_empty_blocks = file.ignore_empty_blocks;
+        blockchain.reward_confirmations = file.reward_confirmations;
+        blockchain.metadata = file.metadata;
+        blockchain.network_id = file.network_id;
+        if let Some(policy) = file.reward_policy {
+            blockchain.reward_policy = policy.policy();
+        }
+        if let Some(selection) = file.selection {
+            blockchain.selection = selection.strategy();
+        }
+        blockchain.max_block_transactions = file.max_block_transactions;
+        blockchain.mining_threads = file.mining_threads;
+        blockchain.hash_format = file.hash_format;
+        Ok(blockchain)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::reward::HalvingReward;
+    use crate::selection::HighestFee;
+    use crate::test_util::TestChainBuilder;
+    use crate::transaction::Transaction;
+
+    fn temp_path(name: &str) -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("rusty_blockchain_{}_{}.json", name, std::process::id()))
+    }
+
+    fn saved(chain: &Blockchain) -> Value {
+        let mut json = Vec::new();
+        chain.write_json(&mut json).unwrap();
+        serde_json::from_slice(&json).unwrap()
+    }
+
+    fn load(value: &Value, name: &str) -> Result<Blockchain, LoadError> {
+        let path = temp_path(name);
+        fs::write(&path, value.to_string()).unwrap();
+        let loaded = Blockchain::load_from_file(&path);
+        fs::remove_file(&path).unwrap();
+        loaded
+    }
+
+    #[test]
+    fn unversioned_file_is_migrated() {
+        let mut chain = Blockchain::new_for_test();
+        chain.mine_pending_transactions("Alice".to_string());
+        let mut value = saved(&chain);
+        value.as_object_mut().unwrap().remove("version");
+
+        let loaded = load(&value, "unversioned").unwrap();
+        assert_eq!(loaded.get_latest_block().hash(), chain.get_latest_block().hash());
+        assert_eq!(loaded.get_balance("Alice"), Ok(100.0));
+        assert!(loaded.is_chain_valid());
+    }
+
+    #[test]
+    fn newer_version_is_refused() {
+        let chain = Blockchain::new_for_test();
+        let mut value = saved(&chain);
+        value["version"] = Value::from(FORMAT_VERSION + 1);
+
+        assert!(matches!(
+            load(&value, "newer"),
+            Err(LoadError::UnsupportedVersion(v)) if v == FORMAT_VERSION + 1
+        ));
+    }
+
+    #[test]
+    fn streamed_json_loads_back_to_the_same_chain() {
+        let mut chain = Blockchain::new_for_test();
+        chain.mine_pending_transactions("Alice".to_string());
+        chain.add_transaction(Transaction::new("Alice".to_string(), "Bob".to_string(), 10.0)).unwrap();
+        chain.mine_pending_transactions("Miner".to_string());
+        chain.add_transaction(Transaction::new("Bob".to_string(), "Carol".to_string(), 4.0)).unwrap();
+
+        let path = temp_path("streamed");
+        chain.write_json(fs::File::create(&path).unwrap()).unwrap();
+        let loaded = Blockchain::load_from_file(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(loaded.get_latest_block().hash(), chain.get_latest_block().hash());
+        assert_eq!(loaded.pending_transactions().len(), 1);
+        assert_eq!(loaded.pending_transactions()[0].id(), chain.pending_transactions()[0].id());
+        assert_eq!(loaded.balances(), chain.balances());
+    }
+
+    #[test]
+    fn size_estimates_grow_with_the_chain() {
+        let mut chain = Blockchain::new_for_test();
+        chain.mine_pending_transactions("Alice".to_string());
+        let short_bytes = chain.estimated_size_bytes();
+        let short_on_disk = [Format::Json, Format::Binary].map(|format| chain.estimated_size_on_disk(format));
+        assert_eq!(short_on_disk[1], short_bytes + 2 * BINARY_HEADER_BYTES);
+
+        chain.add_transaction(Transaction::new("Alice".to_string(), "Bob".to_string(), 1.0)).unwrap();
+        chain.mine_pending_transactions("Miner".to_string());
+
+        assert!(chain.estimated_size_bytes() > short_bytes);
+        for (format, short) in [Format::Json, Format::Binary].into_iter().zip(short_on_disk) {
+            assert!(chain.estimated_size_on_disk(format) > short);
+        }
+    }
+
+    #[test]
+    fn metadata_survives_save_and_load() {
+        let mut chain = Blockchain::new_for_test();
+        chain.mine_pending_transactions("Alice".to_string());
+        chain.set_meta("network".to_string(), "testnet-3".to_string());
+        chain.set_meta("owner".to_string(), "ops".to_string());
+        let path = temp_path("meta");
+
+        chain.save_to_file(&path).unwrap();
+        let loaded = Blockchain::load_from_file(&path);
+        fs::remove_file(&path).unwrap();
+
+        let loaded = loaded.unwrap();
+        assert_eq!(loaded.get_meta("network"), Some("testnet-3"));
+        assert_eq!(loaded.get_meta("owner"), Some("ops"));
+        assert_eq!(loaded.get_meta("missing"), None);
+    }
+
+    #[test]
+    fn hex_dump_round_trips() {
+        let mut chain = Blockchain::new_for_test();
+        chain.mine_pending_transactions("Alice".to_string());
+        chain.add_transaction(Transaction::new("Alice".to_string(), "Bob".to_string(), 10.0)).unwrap();
+        chain.mine_pending_transactions("Miner".to_string());
+        let hex = chain.to_hex();
+        assert!(hex.bytes().all(|c| c.is_ascii_hexdigit() && !c.is_ascii_uppercase()));
+
+        let loaded = Blockchain::from_hex(&hex).unwrap();
+        assert_eq!(loaded.chain_hash(), chain.chain_hash());
+        assert_eq!(loaded.balances(), chain.balances());
+    }
+
+    #[test]
+    fn broken_hex_dump_is_refused() {
+        let hex = Blockchain::new_for_test().to_hex();
+
+        assert!(matches!(Blockchain::from_hex(&hex[1..]), Err(LoadError::Parse(_))));
+        assert!(matches!(Blockchain::from_hex(&hex.replacen('7', "z", 1)), Err(LoadError::Parse(_))));
+        assert!(matches!(Blockchain::from_hex("ff"), Err(LoadError::Parse(_))));
+    }
+
+    #[test]
+    fn base64_hashes_load_back() {
+        let mut chain = Blockchain::new_for_test();
+        chain.mine_pending_transactions("Alice".to_string());
+        chain.set_hash_format(HashFormat::Base64);
+        let value = saved(&chain);
+        assert_eq!(value["chain"][1]["hash"], Value::from(chain.blocks()[1].hash.to_string_in(HashFormat::Base64)));
+
+        let loaded = Blockchain::from_json(&value.to_string()).unwrap();
+        assert_eq!(loaded.chain_hash(), chain.chain_hash());
+        assert!(loaded.is_chain_valid());
+    }
+
+    #[test]
+    fn transaction_feed_writes_one_line_per_transaction() {
+        let chain = TestChainBuilder::new()
+            .mine("Alice")
+            .tx("Alice", "Bob", 30.0)
+            .tx("Alice", "Carol", 20.0)
+            .mine("Miner")
+            .tx("Bob", "Carol", 5.0)
+            .build();
+
+        let mut feed = Vec::new();
+        chain.transaction_feed(&mut feed).unwrap();
+        let lines: Vec<&str> = std::str::from_utf8(&feed).unwrap().lines().collect();
+        assert_eq!(lines.len() as u64, chain.total_transactions());
+
+        let ids: Vec<String> = lines.iter()
+            .map(|line| serde_json::from_str::<Transaction>(line).unwrap().id())
+            .collect();
+        let confirmed: Vec<String> = chain.blocks().iter()
+            .flat_map(|block| &block.transactions)
+            .map(Transaction::id)
+            .collect();
+        assert_eq!(ids, confirmed);
+    }
+
+    #[test]
+    fn loaded_difficulty_bounds_are_normalised() {
+        let mut value = saved(&TestChainBuilder::new().build());
+        value["min_difficulty"] = Value::from(9);
+        value["max_difficulty"] = Value::from(100);
+
+        let loaded = Blockchain::from_json(&value.to_string()).unwrap();
+        assert_eq!((loaded.min_difficulty, loaded.max_difficulty), (9, DEFAULT_MAX_DIFFICULTY));
+
+        value["min_difficulty"] = Value::from(5);
+        value["max_difficulty"] = Value::from(3);
+        let loaded = Blockchain::from_json(&value.to_string()).unwrap();
+        assert_eq!((loaded.min_difficulty, loaded.max_difficulty), (3, 3));
+    }
+
+    #[test]
+    fn halving_chain_keeps_its_settings_through_a_round_trip() {
+        let mut chain = Blockchain::new_for_test();
+        chain.set_reward_policy(Box::new(HalvingReward { initial: 100.0, interval: 2 }));
+        chain.set_selection_strategy(Box::new(HighestFee));
+        chain.set_max_block_transactions(5);
+        chain.set_mining_threads(2);
+        chain.set_hash_format(HashFormat::Base64);
+        for _ in 0..4 {
+            chain.mine_pending_transactions("Miner".to_string());
+        }
+        assert_eq!(chain.get_balance("Miner"), Ok(100.0 + 50.0 + 50.0 + 25.0));
+
+        let path = temp_path("halving");
+        chain.save_to_file(&path).unwrap();
+        let from_file = Blockchain::load_from_file(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        let from_hex = Blockchain::from_hex(&chain.to_hex()).unwrap();
+
+        for mut loaded in [from_file, from_hex] {
+            assert!(loaded.is_chain_valid());
+            assert_eq!(loaded.reward_policy.setting(), Some(RewardSetting::Halving { initial: 100.0, interval: 2 }));
+            assert_eq!(loaded.selection.setting(), Some(SelectionSetting::HighestFee));
+            assert_eq!((loaded.max_block_transactions, loaded.mining_threads), (5, 2));
+            assert_eq!(loaded.hash_format, HashFormat::Base64);
+
+            loaded.mine_pending_transactions("Miner".to_string());
+            assert_eq!(loaded.get_balance("Miner"), Ok(225.0 + 25.0));
+            assert!(loaded.is_chain_valid());
+        }
+    }
+
+    #[test]
+    fn version_1_file_loads_with_the_default_mining_settings() {
+        let chain = TestChainBuilder::new().mine("Alice").build();
+        let mut value = saved(&chain);
+        value["version"] = Value::from(1);
+        for field in ["reward_policy", "selection", "max_block_transactions", "mining_threads", "hash_format"] {
+            value.as_object_mut().unwrap().remove(field);
+        }
+
+        let loaded = Blockchain::from_json(&value.to_string()).unwrap();
+        assert_eq!(loaded.reward_policy.setting(), Some(RewardSetting::Fixed { subsidy: chain.mining_reward }));
+        assert_eq!(loaded.selection.setting(), Some(SelectionSetting::Fifo));
+        assert_eq!((loaded.max_block_transactions, loaded.mining_threads), (usize::MAX, 1));
+        assert!(loaded.is_chain_valid());
+    }
+}
@@ -0,0 +1,83 @@
+use serde::{Deserialize, Serialize};
+
+// Decides the coinbase amount of the block mined at `height`, given the
+// fees its transactions pay. Whatever is returned is split among the
+// reward recipients.
+pub trait RewardPolicy {
+    fn reward(&self, height: u64, fees: f64) -> f64;
+
+    // The policy as saved with the chain. Custom policies can't be written
+    // out, so a chain using one loads back with FixedReward(mining_reward)
+    // and needs set_reward_policy again.
+    fn setting(&self) -> Option<RewardSetting> {
+        None
+    }
+}
+
+// A built-in policy and its parameters, as stored in a saved chain
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum RewardSetting {
+    Fixed { subsidy: f64 },
+    Halving { initial: f64, interval: u64 },
+}
+
+impl RewardSetting {
+    pub(crate) fn policy(&self) -> Box<dyn RewardPolicy> {
+        match *self {
+            RewardSetting::Fixed { subsidy } => Box::new(FixedReward(subsidy)),
+            RewardSetting::Halving { initial, interval } => Box::new(HalvingReward { initial, interval }),
+        }
+    }
+}
+
+// The same subsidy for every block, plus the fees
+pub struct FixedReward(pub f64);
+
+impl RewardPolicy for FixedReward {
+    fn reward(&self, _height: u64, fees: f64) -> f64 {
+        self.0 + fees
+    }
+
+    fn setting(&self) -> Option<RewardSetting> {
+        Some(RewardSetting::Fixed { subsidy: self.0 })
+    }
+}
+
+// Subsidy starts at `initial` and halves every `interval` blocks, plus the fees
+pub struct HalvingReward {
+    pub initial: f64,
+    pub interval: u64,
+}
+
+impl RewardPolicy for HalvingReward {
+    fn reward(&self, height: u64, fees: f64) -> f64 {
+        let halvings = height / self.interval.max(1);
+        let subsidy = if halvings >= 64 { 0.0 } else { self.initial / (1u64 << halvings) as f64 };
+        subsidy + fees
+    }
+
+    fn setting(&self) -> Option<RewardSetting> {
+        Some(RewardSetting::Halving { initial: self.initial, interval: self.interval })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fixed_reward_adds_the_fees() {
+        assert_eq!(FixedReward(50.0).reward(7, 1.5), 51.5);
+    }
+
+    #[test]
+    fn halving_reward_halves_every_interval() {
+        let policy = HalvingReward { initial: 100.0, interval: 10 };
+
+        assert_eq!(policy.reward(9, 0.0), 100.0);
+        assert_eq!(policy.reward(10, 0.0), 50.0);
+        assert_eq!(policy.reward(25, 2.0), 27.0);
+        assert_eq!(policy.reward(640, 2.0), 2.0);
+    }
+}
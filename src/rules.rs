@@ -0,0 +1,124 @@
+use crate::block::Block;
+use crate::blockchain::{Blockchain, ValidationLevel};
+use crate::error::ValidationError;
+use crate::transaction::Transaction;
+
+// Extra consensus rules to replay an existing chain against, e.g. to see
+// whether a proposed soft fork would have rejected any of its blocks
+
+// A rule every transaction in a mined block must satisfy, reward
+// transactions included. `height` is the index of the containing block.
+pub trait TransactionValidator {
+    fn check(&self, tx: &Transaction, height: u64) -> Result<(), String>;
+}
+
+// Lowest difficulty the next block may be mined at, given every block
+// before it (genesis first)
+pub trait DifficultyPolicy {
+    fn required_difficulty(&self, previous: &[Block]) -> usize;
+}
+
+// Rules applied by validate_under_rules; every one of them has to pass
+#[derive(Default)]
+pub struct Rules<'a> {
+    pub validators: &'a [&'a dyn TransactionValidator],
+    pub difficulty: &'a [&'a dyn DifficultyPolicy],
+}
+
+impl Blockchain {
+    // Full validation plus `rules` for every block after genesis. The chain
+    // itself is left as it is; the first block breaking a rule is reported.
+    pub fn validate_under_rules(&self, rules: &Rules) -> Result<(), ValidationError> {
+        self.validate_with(ValidationLevel::Full)?;
+
+        for (i, block) in self.chain.iter().enumerate().skip(1) {
+            for policy in rules.difficulty {
+                let required = policy.required_difficulty(&self.chain[..i]);
+                if block.difficulty < required {
+                    return Err(ValidationError::DifficultyTooLow {
+                        index: block.index,
+                        hash: block.hash,
+                        required,
+                        got: block.difficulty,
+                    });
+                }
+            }
+
+            for tx in &block.transactions {
+                for validator in rules.validators {
+                    validator.check(tx, block.index).map_err(|reason| ValidationError::RuleViolation {
+                        index: block.index,
+                        hash: block.hash,
+                        tx_id: tx.id(),
+                        reason,
+                    })?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Refuses transfers above the limit, rewards excepted
+    struct MaxAmount(f64);
+
+    impl TransactionValidator for MaxAmount {
+        fn check(&self, tx: &Transaction, _height: u64) -> Result<(), String> {
+            if !tx.is_system() && tx.amount > self.0 {
+                return Err(format!("amount {} above {}", tx.amount, self.0));
+            }
+            Ok(())
+        }
+    }
+
+    // The same minimum difficulty for every block after genesis
+    struct AtLeast(usize);
+
+    impl DifficultyPolicy for AtLeast {
+        fn required_difficulty(&self, _previous: &[Block]) -> usize {
+            self.0
+        }
+    }
+
+    fn chain() -> Blockchain {
+        let mut chain = Blockchain::new_for_test();
+        chain.mine_pending_transactions("Alice".to_string());
+        chain.add_transaction(Transaction::new("Alice".to_string(), "Bob".to_string(), 50.0)).unwrap();
+        chain.mine_pending_transactions("Miner".to_string());
+        chain
+    }
+
+    #[test]
+    fn strict_rules_reject_the_chain() {
+        let chain = chain();
+        let tx_id = chain.blocks()[2].transactions()[0].id();
+
+        let strict = Rules { validators: &[&MaxAmount(10.0)], difficulty: &[] };
+        assert_eq!(chain.validate_under_rules(&strict), Err(ValidationError::RuleViolation {
+            index: 2,
+            hash: chain.blocks()[2].hash,
+            tx_id,
+            reason: "amount 50 above 10".to_string(),
+        }));
+
+        let strict = Rules { validators: &[], difficulty: &[&AtLeast(1)] };
+        assert!(matches!(
+            chain.validate_under_rules(&strict),
+            Err(ValidationError::DifficultyTooLow { index: 1, required: 1, got: 0, .. })
+        ));
+    }
+
+    #[test]
+    fn lenient_rules_pass() {
+        let chain = chain();
+        let lenient = Rules { validators: &[&MaxAmount(100.0)], difficulty: &[&AtLeast(0)] };
+
+        assert_eq!(chain.validate_under_rules(&lenient), Ok(()));
+        assert_eq!(chain.validate_under_rules(&Rules::default()), Ok(()));
+    }
+}
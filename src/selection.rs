@@ -0,0 +1,163 @@
+use serde::{Deserialize, Serialize};
+use std::cell::Cell;
+
+use crate::transaction::Transaction;
+
+// Decides which ready transactions go into the next block. `pending` only
+// holds transactions whose nonces are in order, and at most `max` may be
+// returned. Picking a sender's later nonce without its earlier one just
+// leaves both in the pool, as does picking a transaction that spends a
+// pending credit without the transaction paying it.
+pub trait SelectionStrategy {
+    fn select<'a>(&self, pending: &'a [Transaction], max: usize) -> Vec<&'a Transaction>;
+
+    // The strategy as saved with the chain. A chain using a custom strategy
+    // loads back with Fifo.
+    fn setting(&self) -> Option<SelectionSetting> {
+        None
+    }
+}
+
+// A built-in strategy, as stored in a saved chain. SeededRandom keeps its
+// current state, so a reloaded chain carries on with the same sequence.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum SelectionSetting {
+    Fifo,
+    HighestFee,
+    SeededRandom { state: u64 },
+}
+
+impl SelectionSetting {
+    pub(crate) fn strategy(&self) -> Box<dyn SelectionStrategy> {
+        match *self {
+            SelectionSetting::Fifo => Box::new(Fifo),
+            SelectionSetting::HighestFee => Box::new(HighestFee),
+            SelectionSetting::SeededRandom { state } => Box::new(SeededRandom::new(state)),
+        }
+    }
+}
+
+// Oldest first
+pub struct Fifo;
+
+impl SelectionStrategy for Fifo {
+    fn select<'a>(&self, pending: &'a [Transaction], max: usize) -> Vec<&'a Transaction> {
+        pending.iter().take(max).collect()
+    }
+
+    fn setting(&self) -> Option<SelectionSetting> {
+        Some(SelectionSetting::Fifo)
+    }
+}
+
+// Largest fee first. Equal fees are ordered by transaction id so every node
+// picks the same transactions from the same pool, whatever order they
+// arrived in.
+pub struct HighestFee;
+
+impl SelectionStrategy for HighestFee {
+    fn select<'a>(&self, pending: &'a [Transaction], max: usize) -> Vec<&'a Transaction> {
+        let mut by_fee: Vec<(&Transaction, String)> = pending.iter().map(|tx| (tx, tx.id())).collect();
+        by_fee.sort_by(|(a, a_id), (b, b_id)| b.fee.total_cmp(&a.fee).then_with(|| a_id.cmp(b_id)));
+        by_fee.into_iter().take(max).map(|(tx, _)| tx).collect()
+    }
+
+    fn setting(&self) -> Option<SelectionSetting> {
+        Some(SelectionSetting::HighestFee)
+    }
+}
+
+// Random order that is the same for the same seed, for reproducible tests.
+// Each call to select moves the generator on, so successive blocks still
+// differ. SplitMix64 is plenty here and keeps rand out of the dependencies.
+pub struct SeededRandom {
+    state: Cell<u64>,
+}
+
+impl SeededRandom {
+    pub fn new(seed: u64) -> Self {
+        Self { state: Cell::new(seed) }
+    }
+
+    pub(crate) fn next_u64(&self) -> u64 {
+        let state = self.state.get().wrapping_add(0x9e37_79b9_7f4a_7c15);
+        self.state.set(state);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+}
+
+impl SelectionStrategy for SeededRandom {
+    fn select<'a>(&self, pending: &'a [Transaction], max: usize) -> Vec<&'a Transaction> {
+        // Fisher-Yates shuffle
+        let mut shuffled: Vec<&Transaction> = pending.iter().collect();
+        for i in (1..shuffled.len()).rev() {
+            let j = (self.next_u64() % (i as u64 + 1)) as usize;
+            shuffled.swap(i, j);
+        }
+        shuffled.truncate(max);
+        shuffled
+    }
+
+    fn setting(&self) -> Option<SelectionSetting> {
+        Some(SelectionSetting::SeededRandom { state: self.state.get() })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn with_fees(fees: &[f64]) -> Vec<Transaction> {
+        fees.iter()
+            .enumerate()
+            .map(|(i, fee)| Transaction::new(format!("Sender{}", i), "Bob".to_string(), 1.0).with_fee(*fee))
+            .collect()
+    }
+
+    fn fees(selected: &[&Transaction]) -> Vec<f64> {
+        selected.iter().map(|tx| tx.fee).collect()
+    }
+
+    #[test]
+    fn highest_fee_picks_the_top_fees_under_the_limit() {
+        let pending = with_fees(&[0.1, 2.0, 0.5, 3.0, 1.0]);
+        assert_eq!(fees(&HighestFee.select(&pending, 3)), vec![3.0, 2.0, 1.0]);
+    }
+
+    #[test]
+    fn fifo_keeps_pool_order() {
+        let pending = with_fees(&[0.1, 2.0, 0.5]);
+        assert_eq!(fees(&Fifo.select(&pending, 2)), vec![0.1, 2.0]);
+    }
+
+    #[test]
+    fn equal_fees_are_ordered_by_id_whatever_the_insertion_order() {
+        let pending = with_fees(&[1.0, 1.0, 1.0, 1.0]);
+        let mut reversed = pending.clone();
+        reversed.reverse();
+
+        let ids = |selected: Vec<&Transaction>| selected.iter().map(|tx| tx.id()).collect::<Vec<_>>();
+        let forward = ids(HighestFee.select(&pending, 3));
+        assert_eq!(forward, ids(HighestFee.select(&reversed, 3)));
+
+        let mut all: Vec<String> = pending.iter().map(Transaction::id).collect();
+        all.sort();
+        assert_eq!(forward, all[..3]);
+    }
+
+    #[test]
+    fn same_seed_selects_the_same_transactions() {
+        let pending = with_fees(&[0.1, 0.2, 0.3, 0.4, 0.5, 0.6, 0.7, 0.8]);
+        let (first, second) = (SeededRandom::new(42), SeededRandom::new(42));
+
+        for _ in 0..3 {
+            assert_eq!(fees(&first.select(&pending, 4)), fees(&second.select(&pending, 4)));
+        }
+        assert_ne!(fees(&SeededRandom::new(42).select(&pending, 8)), fees(&SeededRandom::new(7).select(&pending, 8)));
+        assert_eq!(first.select(&pending, 8).len(), 8);
+    }
+}
@@ -0,0 +1,94 @@
+use crate::blockchain::Blockchain;
+use crate::selection::SeededRandom;
+
+// Stake-weighted choice of who proposes the next block, for experimenting
+// with proof of stake. Blocks are still mined and checked with proof of
+// work: there are no keys to sign a proposal with, so nothing here is
+// enforced by validation.
+
+impl Blockchain {
+    // Pick one of `stakers` with odds proportional to their spendable
+    // balance, seeded from the tip's hash. The same tip and balances always
+    // give the same proposer, whatever order `stakers` is listed in. None if
+    // no staker has a positive balance.
+    pub fn select_proposer(&self, stakers: &[String]) -> Option<String> {
+        let mut stakes: Vec<(&String, f64)> = stakers.iter()
+            .map(|address| (address, self.spendable_balance(address)))
+            .filter(|(_, stake)| *stake > 0.0)
+            .collect();
+        stakes.sort_by_key(|(address, _)| *address);
+        stakes.dedup_by(|(a, _), (b, _)| a == b);
+
+        let total: f64 = stakes.iter().map(|(_, stake)| stake).sum();
+        if total <= 0.0 {
+            return None;
+        }
+
+        let tip = self.get_latest_block().hash;
+        let seed = u64::from_be_bytes(tip.as_bytes()[..8].try_into().unwrap());
+        // Top 53 bits give an evenly spread f64 in [0, 1)
+        let draw = (SeededRandom::new(seed).next_u64() >> 11) as f64 / (1u64 << 53) as f64 * total;
+
+        let mut cumulative = 0.0;
+        for (address, stake) in &stakes {
+            cumulative += stake;
+            if draw < cumulative {
+                return Some(address.to_string());
+            }
+        }
+        // Rounding can leave the draw just past the last running total
+        stakes.last().map(|(address, _)| address.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_util::TestChainBuilder;
+
+    fn stakers(addresses: &[&str]) -> Vec<String> {
+        addresses.iter().map(|address| address.to_string()).collect()
+    }
+
+    #[test]
+    fn same_tip_and_balances_pick_the_same_proposer() {
+        let chain = TestChainBuilder::new()
+            .mine("Alice")
+            .mine("Bob")
+            .mine("Carol")
+            .tx("Alice", "Dave", 40.0)
+            .mine("Carol")
+            .build();
+
+        let proposer = chain.select_proposer(&stakers(&["Alice", "Bob", "Carol", "Dave"]));
+        assert!(proposer.is_some());
+        assert_eq!(chain.select_proposer(&stakers(&["Dave", "Carol", "Bob", "Alice", "Bob"])), proposer);
+    }
+
+    #[test]
+    fn only_stakers_with_a_balance_can_propose() {
+        let chain = TestChainBuilder::new().mine("Alice").build();
+
+        assert_eq!(chain.select_proposer(&stakers(&["Alice", "Nobody"])), Some("Alice".to_string()));
+        assert_eq!(chain.select_proposer(&stakers(&["Nobody"])), None);
+        assert_eq!(chain.select_proposer(&[]), None);
+    }
+
+    #[test]
+    fn odds_follow_the_stake() {
+        // Over many different tips, a staker holding 9 times the balance
+        // should be picked far more often
+        let stakers = stakers(&["Alice", "Bob"]);
+        let mut bob = 0;
+        for i in 0..200 {
+            let mut builder = TestChainBuilder::new().mine("Alice");
+            for _ in 0..9 {
+                builder = builder.mine("Bob");
+            }
+            let chain = builder.mine(&format!("Miner{}", i)).build();
+            if chain.select_proposer(&stakers).as_deref() == Some("Bob") {
+                bob += 1;
+            }
+        }
+        assert!(bob > 150, "Bob picked {} times out of 200", bob);
+    }
+}
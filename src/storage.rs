@@ -0,0 +1,122 @@
+use std::fs::{self, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::PathBuf;
+
+use crate::block::Block;
+use crate::blockchain::Blockchain;
+
+// Where a chain keeps its data. Blockchain calls save_block for every block
+// it appends; load_chain and save_state are for whoever owns the backend.
+pub trait Storage {
+    fn save_block(&mut self, block: &Block) -> io::Result<()>;
+    fn load_chain(&self) -> io::Result<Vec<Block>>;
+    fn save_state(&mut self, blockchain: &Blockchain) -> io::Result<()>;
+}
+
+// Default backend: blocks are appended to `blocks.jsonl` (one JSON block per
+// line) and full snapshots go to `chain.json` in the same directory
+pub struct FileStorage {
+    dir: PathBuf,
+}
+
+impl FileStorage {
+    pub fn new<P: Into<PathBuf>>(dir: P) -> io::Result<Self> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir)?;
+        Ok(Self { dir })
+    }
+
+    fn blocks_path(&self) -> PathBuf {
+        self.dir.join("blocks.jsonl")
+    }
+}
+
+impl Storage for FileStorage {
+    fn save_block(&mut self, block: &Block) -> io::Result<()> {
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(self.blocks_path())?;
+        let line = serde_json::to_string(block).map_err(io::Error::other)?;
+        writeln!(file, "{}", line)
+    }
+
+    fn load_chain(&self) -> io::Result<Vec<Block>> {
+        let file = match fs::File::open(self.blocks_path()) {
+            Ok(file) => file,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e),
+        };
+
+        let mut blocks = Vec::new();
+        for line in BufReader::new(file).lines() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            blocks.push(serde_json::from_str(&line).map_err(io::Error::other)?);
+        }
+        Ok(blocks)
+    }
+
+    fn save_state(&mut self, blockchain: &Blockchain) -> io::Result<()> {
+        blockchain.save_to_file(self.dir.join("chain.json"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    // Keeps saved blocks where the test can still see them
+    struct MemoryStorage {
+        blocks: Rc<RefCell<Vec<Block>>>,
+    }
+
+    impl Storage for MemoryStorage {
+        fn save_block(&mut self, block: &Block) -> io::Result<()> {
+            self.blocks.borrow_mut().push(block.clone());
+            Ok(())
+        }
+
+        fn load_chain(&self) -> io::Result<Vec<Block>> {
+            Ok(self.blocks.borrow().clone())
+        }
+
+        fn save_state(&mut self, _blockchain: &Blockchain) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn mined_blocks_are_saved_as_they_come() {
+        let blocks = Rc::new(RefCell::new(Vec::new()));
+        let mut chain = Blockchain::new_for_test();
+        chain.set_storage(Box::new(MemoryStorage { blocks: Rc::clone(&blocks) }));
+
+        chain.mine_pending_transactions("Alice".to_string());
+        assert_eq!(blocks.borrow().len(), 1);
+        chain.mine_pending_transactions("Alice".to_string());
+
+        let saved: Vec<u64> = blocks.borrow().iter().map(Block::index).collect();
+        assert_eq!(saved, vec![1, 2]);
+        assert_eq!(blocks.borrow()[1].hash(), chain.get_latest_block().hash());
+    }
+
+    #[test]
+    fn file_storage_reads_back_what_it_saved() {
+        let dir = std::env::temp_dir().join(format!("rusty_blockchain_storage_{}", std::process::id()));
+        let mut storage = FileStorage::new(&dir).unwrap();
+        assert!(storage.load_chain().unwrap().is_empty());
+
+        let chain = Blockchain::new_for_test();
+        storage.save_block(chain.get_latest_block()).unwrap();
+        let loaded = storage.load_chain().unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(loaded.len(), 1);
+        assert_eq!(loaded[0].hash(), chain.get_latest_block().hash());
+    }
+}
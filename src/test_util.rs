@@ -0,0 +1,193 @@
+use std::cell::Cell;
+
+use crate::block::Block;
+use crate::blockchain::Blockchain;
+use crate::error::ChainError;
+use crate::transaction::{Transaction, SYSTEM_ADDRESS};
+
+// Clock the builder starts from, and how far it moves per mined block
+const START_TIME: i64 = 1_700_000_000;
+const BLOCK_INTERVAL: i64 = 60;
+
+// Builds a chain for tests in a few chained calls:
+//
+//     let chain = TestChainBuilder::new()
+//         .mine("Alice")
+//         .tx("Alice", "Bob", 10.0)
+//         .mine("Miner")
+//         .build();
+//
+// Blocks are mined at difficulty 0, so no nonce search, and every timestamp
+// comes from a fixed clock, so the same calls always give the same hashes.
+// Blockchain::new_for_test mines on the same clock, for tests that need the
+// mining calls themselves. Transactions go through the usual pool checks and
+// the builder panics if one is refused.
+pub struct TestChainBuilder {
+    chain: Blockchain,
+    now: i64,
+}
+
+impl TestChainBuilder {
+    pub fn new() -> Self {
+        Self::with_reward(100.0)
+    }
+
+    pub fn with_reward(mining_reward: f64) -> Self {
+        Self {
+            chain: Blockchain::with_genesis_timestamp(0, mining_reward, START_TIME).unwrap(),
+            now: START_TIME,
+        }
+    }
+
+    // Queue a transfer for the next mined block, with the sender's next nonce
+    pub fn tx(mut self, from: &str, to: &str, amount: f64) -> Self {
+        let mut tx = Transaction::new(from.to_string(), to.to_string(), amount)
+            .with_nonce(self.chain.next_nonce(from));
+        tx.timestamp = self.now;
+        if let Err(e) = self.chain.add_transaction(tx) {
+            panic!("TestChainBuilder: {} -> {} refused: {}", from, to, e);
+        }
+        self
+    }
+
+    // Mine everything queued so far (possibly nothing) into one block that
+    // pays `miner` the reward plus fees
+    pub fn mine(mut self, miner: &str) -> Self {
+        self.now += BLOCK_INTERVAL;
+
+        let mut transactions = std::mem::take(&mut self.chain.pending_transactions);
+        let fees: f64 = transactions.iter().map(|tx| tx.fee).sum();
+        let mut reward = Transaction::new(SYSTEM_ADDRESS.to_string(), miner.to_string(), self.chain.block_reward(self.chain.chain.len() as u64, fees));
+        reward.timestamp = self.now;
+        transactions.push(reward);
+
+        let latest = self.chain.get_latest_block();
+        let mut block = Block::new(latest.index + 1, transactions, latest.hash, self.chain.difficulty);
+        block.timestamp = self.now;
+        block.hash = block.calculate_hash();
+        block.mine_block();
+
+        if let Err(e) = self.chain.add_block(block) {
+            panic!("TestChainBuilder: mined block refused: {}", e);
+        }
+        self
+    }
+
+    pub fn build(self) -> Blockchain {
+        self.chain
+    }
+}
+
+impl Default for TestChainBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Blockchain {
+    // Difficulty 0 chain (every block is mined on the first hash) with a
+    // reward of 100, on the same fixed clock as TestChainBuilder: the
+    // genesis block is stamped START_TIME and every mined block
+    // BLOCK_INTERVAL after the one before. Transactions still carry the
+    // time they were made, so set their timestamps too when the hashes need
+    // to be reproducible.
+    pub fn new_for_test() -> Self {
+        let mut chain = Self::with_genesis_timestamp(0, 100.0, START_TIME).unwrap();
+        let now = Cell::new(START_TIME);
+        chain.set_clock(Box::new(move || {
+            now.set(now.get() + BLOCK_INTERVAL);
+            now.get()
+        }));
+        chain
+    }
+
+    // Overwrite the amount of an already mined transaction without re-mining,
+    // to stage an attack that validation should then catch
+    pub fn tamper_transaction(&mut self, block: u64, tx: usize, new_amount: f64) -> Result<(), ChainError> {
+        let transactions = &mut self.chain.get_mut(block as usize)
+            .ok_or(ChainError::NoSuchBlock { index: block })?
+            .transactions;
+        let transaction = transactions.get_mut(tx).ok_or(ChainError::NoSuchTransaction { block, tx })?;
+        transaction.amount = new_amount;
+        // Keep the derived indexes in line with the edited history
+        self.reindex();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn three_blocks() -> Blockchain {
+        TestChainBuilder::new()
+            .mine("Alice")
+            .tx("Alice", "Bob", 30.0)
+            .mine("Miner")
+            .tx("Bob", "Carol", 10.0)
+            .mine("Miner")
+            .build()
+    }
+
+    #[test]
+    fn builds_a_three_block_chain() {
+        let chain = three_blocks();
+
+        assert_eq!(chain.height(), 3);
+        assert_eq!(chain.get_balance("Alice"), Ok(70.0));
+        assert_eq!(chain.get_balance("Bob"), Ok(20.0));
+        assert_eq!(chain.get_balance("Carol"), Ok(10.0));
+        assert_eq!(chain.get_balance("Miner"), Ok(200.0));
+        assert!(chain.is_chain_valid());
+    }
+
+    #[test]
+    fn same_calls_give_the_same_hashes() {
+        assert_eq!(three_blocks().chain_hash(), three_blocks().chain_hash());
+    }
+
+    #[test]
+    fn tampering_breaks_validation() {
+        let mut chain = three_blocks();
+
+        chain.tamper_transaction(2, 0, 1000.0).unwrap();
+        assert_eq!(chain.get_balance("Bob"), Ok(990.0));
+        assert!(!chain.is_chain_valid());
+
+        assert_eq!(chain.tamper_transaction(9, 0, 1.0), Err(ChainError::NoSuchBlock { index: 9 }));
+        assert_eq!(chain.tamper_transaction(2, 5, 1.0), Err(ChainError::NoSuchTransaction { block: 2, tx: 5 }));
+    }
+
+    #[test]
+    fn new_for_test_mines_without_searching() {
+        let started = std::time::Instant::now();
+        let mut chain = Blockchain::new_for_test();
+        chain.mine_blocks(20, "Miner");
+
+        assert_eq!(chain.height(), 20);
+        assert!(chain.blocks().iter().all(|block| block.nonce == 0));
+        assert!(chain.is_chain_valid());
+        // Generous, so a slow machine doesn't fail it
+        assert!(started.elapsed() < std::time::Duration::from_secs(2));
+    }
+
+    #[test]
+    fn new_for_test_mines_on_the_fixed_clock() {
+        let mine_two = || {
+            let mut chain = Blockchain::new_for_test();
+            chain.mine_pending_transactions("Alice".to_string());
+            chain.mine_pending_transactions("Bob".to_string());
+            chain
+        };
+        let chain = mine_two();
+
+        let timestamps: Vec<i64> = chain.blocks().iter().map(Block::timestamp).collect();
+        assert_eq!(timestamps, vec![START_TIME, START_TIME + BLOCK_INTERVAL, START_TIME + 2 * BLOCK_INTERVAL]);
+        assert_eq!(chain.blocks()[1].transactions[0].timestamp, START_TIME + BLOCK_INTERVAL);
+        assert_eq!(chain.chain_hash(), mine_two().chain_hash());
+        assert_eq!(
+            chain.get_latest_block().hash,
+            TestChainBuilder::new().mine("Alice").mine("Bob").build().get_latest_block().hash
+        );
+    }
+}
@@ -0,0 +1,299 @@
+use serde::{Serialize, Deserialize};
+use serde_json::Value;
+use chrono::Utc;
+
+use crate::error::ParseError;
+use crate::merkle;
+
+// Sender used for genesis and mining reward transactions
+pub const SYSTEM_ADDRESS: &str = "System";
+
+// Receiver of the genesis block's zero-amount transaction
+pub(crate) const GENESIS_ADDRESS: &str = "Genesis";
+
+// Transaction structure
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Transaction {
+    pub(crate) sender: String,
+    pub(crate) receiver: String,
+    pub(crate) amount: f64,
+    // Paid by the sender on top of `amount` and collected by the miner
+    #[serde(default)]
+    pub(crate) fee: f64,
+    pub(crate) timestamp: i64,
+    // Per-sender sequence number, must go 0, 1, 2, ... for each sender
+    #[serde(default)]
+    pub(crate) nonce: u64,
+    // Free-form note, e.g. the message a miner embeds in its reward transaction
+    #[serde(default)]
+    pub(crate) memo: Option<String>,
+    // Receivers of a batch payout. When set, `receiver` is empty and
+    // `amount` is the sum of the payouts.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub(crate) payouts: Vec<(String, f64)>,
+    // Network the transaction is meant for. It is part of the id, so a
+    // transaction made for one network can't be replayed on another.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) network_id: Option<String>,
+}
+
+impl Transaction {
+    pub fn new(sender: String, receiver: String, amount: f64) -> Self {
+        Self {
+            sender,
+            receiver,
+            amount,
+            fee: 0.0,
+            timestamp: Utc::now().timestamp(),
+            nonce: 0,
+            memo: None,
+            payouts: Vec::new(),
+            network_id: None,
+        }
+    }
+
+    // One transaction paying several receivers. The sender is debited the
+    // total (plus the fee) once.
+    pub fn batch(sender: String, payouts: Vec<(String, f64)>) -> Self {
+        let amount = payouts.iter().map(|(_, amount)| amount).sum();
+        Self {
+            payouts,
+            ..Self::new(sender, String::new(), amount)
+        }
+    }
+
+    pub fn with_fee(mut self, fee: f64) -> Self {
+        self.fee = fee;
+        self
+    }
+
+    pub fn with_nonce(mut self, nonce: u64) -> Self {
+        self.nonce = nonce;
+        self
+    }
+
+    pub fn with_memo(mut self, memo: String) -> Self {
+        self.memo = Some(memo);
+        self
+    }
+
+    pub fn with_network_id(mut self, network_id: String) -> Self {
+        self.network_id = Some(network_id);
+        self
+    }
+
+    pub fn sender(&self) -> &str {
+        &self.sender
+    }
+
+    // Empty for a batch payout, see outputs
+    pub fn receiver(&self) -> &str {
+        &self.receiver
+    }
+
+    // Every (receiver, amount) this transaction credits: the single
+    // receiver, or each payout of a batch
+    pub fn outputs(&self) -> impl Iterator<Item = (&str, f64)> {
+        let single = self.payouts.is_empty().then_some((self.receiver.as_str(), self.amount));
+        single.into_iter().chain(self.payouts.iter().map(|(receiver, amount)| (receiver.as_str(), *amount)))
+    }
+
+    // Total credited to `address`
+    pub fn received_by(&self, address: &str) -> f64 {
+        self.outputs()
+            .filter(|(receiver, _)| *receiver == address)
+            .map(|(_, amount)| amount)
+            .sum()
+    }
+
+    // Payouts must each be positive and add up to `amount`
+    pub(crate) fn has_valid_payouts(&self) -> bool {
+        let total: f64 = self.payouts.iter().map(|(_, amount)| amount).sum();
+        self.payouts.is_empty()
+            || (self.payouts.iter().all(|(_, amount)| amount.is_finite() && *amount > 0.0) && total == self.amount)
+    }
+
+    pub fn amount(&self) -> f64 {
+        self.amount
+    }
+
+    pub fn fee(&self) -> f64 {
+        self.fee
+    }
+
+    // Everything the sender gives up: amount plus fee
+    pub fn total_cost(&self) -> f64 {
+        self.amount + self.fee
+    }
+
+    pub fn timestamp(&self) -> i64 {
+        self.timestamp
+    }
+
+    pub fn nonce(&self) -> u64 {
+        self.nonce
+    }
+
+    pub fn memo(&self) -> Option<&str> {
+        self.memo.as_deref()
+    }
+
+    pub fn network_id(&self) -> Option<&str> {
+        self.network_id.as_deref()
+    }
+
+    // Identifier of a transaction: the SHA-256 of its canonical encoding
+    pub fn id(&self) -> String {
+        merkle::transaction_hash(self)
+    }
+
+    // Size in bytes of the canonical encoding (memo included)
+    pub fn size(&self) -> usize {
+        self.hash_data().len()
+    }
+
+    pub(crate) fn is_system(&self) -> bool {
+        self.sender == SYSTEM_ADDRESS
+    }
+
+    // The exact bytes hashed into the transaction id
+    pub fn hash_preimage(&self) -> Vec<u8> {
+        self.hash_data().into_bytes()
+    }
+
+    // Canonical encoding used for hashing. Every field is length-prefixed so
+    // that e.g. ("ab", "c") and ("a", "bc") can't produce the same bytes.
+    pub(crate) fn hash_data(&self) -> String {
+        let mut data = String::new();
+        push_field(&mut data, &self.sender);
+        push_field(&mut data, &self.receiver);
+        push_field(&mut data, &self.amount.to_string());
+        push_field(&mut data, &self.fee.to_string());
+        push_field(&mut data, &self.timestamp.to_string());
+        push_field(&mut data, &self.nonce.to_string());
+        match &self.memo {
+            Some(memo) => push_field(&mut data, memo),
+            None => data.push('-'),
+        }
+        // Also left out unless set. No length prefix starts with '@', so
+        // this can't be mistaken for a payout.
+        if let Some(network_id) = &self.network_id {
+            data.push('@');
+            push_field(&mut data, network_id);
+        }
+        // Only batches add anything, so other transactions keep their ids
+        for (receiver, amount) in &self.payouts {
+            push_field(&mut data, receiver);
+            push_field(&mut data, &amount.to_string());
+        }
+        data
+    }
+}
+
+// (field, expected type, required, type check) for parse_transaction
+type FieldCheck = (&'static str, &'static str, bool, fn(&Value) -> bool);
+
+const FIELD_CHECKS: [FieldCheck; 9] = [
+    ("sender", "a string", true, Value::is_string),
+    ("receiver", "a string", true, Value::is_string),
+    ("amount", "a number", true, Value::is_number),
+    ("timestamp", "an integer", true, Value::is_i64),
+    ("fee", "a number", false, Value::is_number),
+    ("nonce", "a non-negative integer", false, Value::is_u64),
+    ("memo", "a string or null", false, |v| v.is_string() || v.is_null()),
+    ("payouts", "an array", false, Value::is_array),
+    ("network_id", "a string or null", false, |v| v.is_string() || v.is_null()),
+];
+
+// Read a transaction from untrusted JSON (e.g. a request body), reporting
+// which field is wrong instead of serde's generic message
+pub fn parse_transaction(json: &str) -> Result<Transaction, ParseError> {
+    let value: Value = serde_json::from_str(json).map_err(|e| ParseError::Malformed(e.to_string()))?;
+    let object = value.as_object().ok_or(ParseError::NotAnObject)?;
+
+    for (field, expected, required, has_type) in FIELD_CHECKS {
+        match object.get(field) {
+            None if required => return Err(ParseError::MissingField { field }),
+            Some(v) if !has_type(v) => return Err(ParseError::WrongType { field, expected }),
+            _ => {}
+        }
+    }
+
+    let transaction: Transaction = serde_json::from_value(value)
+        .map_err(|e| ParseError::Malformed(e.to_string()))?;
+    if transaction.is_system() {
+        return Err(ParseError::SystemSender);
+    }
+    if !transaction.amount.is_finite() || transaction.amount <= 0.0 || !transaction.has_valid_payouts() {
+        return Err(ParseError::InvalidAmount { amount: transaction.amount });
+    }
+    if !transaction.fee.is_finite() || transaction.fee < 0.0 {
+        return Err(ParseError::InvalidFee { fee: transaction.fee });
+    }
+    Ok(transaction)
+}
+
+pub(crate) fn push_field(data: &mut String, value: &str) {
+    data.push_str(&value.len().to_string());
+    data.push(':');
+    data.push_str(value);
+    data.push(';');
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shifted_field_boundaries_hash_differently() {
+        let mut first = Transaction::new("ab".to_string(), "c".to_string(), 1.0);
+        let mut second = Transaction::new("a".to_string(), "bc".to_string(), 1.0);
+        first.timestamp = 1_700_000_000;
+        second.timestamp = 1_700_000_000;
+
+        assert_ne!(first.hash_data(), second.hash_data());
+    }
+
+    #[test]
+    fn memo_adds_its_encoded_length_to_the_size() {
+        let plain = Transaction::new("Alice".to_string(), "Bob".to_string(), 1.0);
+        let noted = plain.clone().with_memo("rent".to_string());
+
+        // "4:rent;" in place of the "-" marking no memo
+        assert_eq!(noted.size() - plain.size(), "4:rent;".len() - 1);
+        assert_eq!(plain.size(), plain.hash_data().len());
+    }
+
+    #[test]
+    fn parse_reports_a_missing_amount() {
+        let json = r#"{"sender": "Alice", "receiver": "Bob", "timestamp": 1700000000}"#;
+        assert_eq!(parse_transaction(json).unwrap_err(), ParseError::MissingField { field: "amount" });
+    }
+
+    #[test]
+    fn parse_reports_a_non_numeric_amount() {
+        let json = r#"{"sender": "Alice", "receiver": "Bob", "amount": "ten", "timestamp": 1700000000}"#;
+        assert_eq!(
+            parse_transaction(json).unwrap_err(),
+            ParseError::WrongType { field: "amount", expected: "a number" }
+        );
+
+        let json = r#"{"sender": "Alice", "receiver": "Bob", "amount": -1, "timestamp": 1700000000}"#;
+        assert_eq!(parse_transaction(json).unwrap_err(), ParseError::InvalidAmount { amount: -1.0 });
+    }
+
+    #[test]
+    fn parse_refuses_a_system_sender() {
+        let json = r#"{"sender": "System", "receiver": "Mallory", "amount": 1000, "timestamp": 1700000000}"#;
+        assert_eq!(parse_transaction(json).unwrap_err(), ParseError::SystemSender);
+    }
+
+    #[test]
+    fn parse_accepts_a_valid_transaction() {
+        let json = r#"{"sender": "Alice", "receiver": "Bob", "amount": 10.5, "timestamp": 1700000000, "nonce": 3}"#;
+        let tx = parse_transaction(json).unwrap();
+
+        assert_eq!((tx.sender(), tx.receiver(), tx.amount()), ("Alice", "Bob", 10.5));
+        assert_eq!((tx.timestamp(), tx.nonce(), tx.fee()), (1_700_000_000, 3, 0.0));
+    }
+}